@@ -1,17 +1,30 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 #[double]
 use crate::tasks::TaskManager;
 use crate::{
+    calendar::{week_lanes, CalendarBar, CalendarPage, CalendarRange},
+    filter::{DueFilter, Expression, SavedView, TaskFilter},
+    grouping::{group_tasks, Grouping},
+    markdown,
+    secrets::secret_env_var,
+    sorting::{SortKey, Sorting, PRESETS},
     sources::{
-        CalDavSource, GitHubSource, GitLabSource, OpenProjectSource, TaskSource, CALDAV_ICON,
-        GITHUB_ICON, GITLAB_ICON, OPENPROJECT_ICON,
+        github::IssueFilter, oauth::DeviceFlow, CalDavSource, GitHubSource, GitLabSource,
+        OpenProjectSource, TaskSource, CALDAV_ICON, GITHUB_ICON, GITLAB_ICON, OPENPROJECT_ICON,
     },
-    tasks::Task,
+    tasks::{Task, TaskKind, TaskReason},
 };
 use chrono::prelude::*;
-use eframe::epaint::ahash::HashSet;
-use egui::{Color32, Context, Layout, RichText, ScrollArea, Slider, TextEdit, Ui, Vec2};
+use eframe::epaint::ahash::{HashMap, HashSet};
+use egui::{
+    text::LayoutJob, Align, Color32, Context, FontSelection, Layout, RichText, ScrollArea, Slider,
+    TextEdit, Ui, Vec2,
+};
 use egui_notify::{Toast, Toasts};
 use ellipse::Ellipse;
 use itertools::Itertools;
@@ -19,29 +32,141 @@ use log::error;
 use minicaldav::Error;
 use mockall_double::double;
 use ureq::ErrorKind;
+use uuid::Uuid;
 
 const BOX_WIDTH: f32 = 220.0;
 
+/// Widths of the columns with a fixed size in the list view. The title
+/// column takes the remaining space.
+const ICON_COLUMN_WIDTH: f32 = 24.0;
+const PROJECT_COLUMN_WIDTH: f32 = 160.0;
+const DUE_COLUMN_WIDTH: f32 = 150.0;
+const AGE_COLUMN_WIDTH: f32 = 50.0;
+
+/// Width of the list of tasks without due date next to the calendar.
+const TRAY_WIDTH: f32 = 200.0;
+
+const FILTER_HELP: &str = "Words are searched in the title, project and description.
+Conditions like source:gitlab, project:\"infra*\", label:bug, kind:mr, status:done,
+assignee:alice, author:bob, priority:high, is:overdue or has:url restrict the tasks.
+Dates can be compared, e.g. due<7d, due<=2024-06-01, created<2w or due:none.
+Put a - in front of a term to exclude matching tasks.";
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 struct Settings {
     refresh_rate_seconds: u64,
+    hide_subtasks: bool,
+    sorting: Sorting,
+    grouping: Grouping,
+    view: TaskView,
+    calendar_range: CalendarRange,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             refresh_rate_seconds: 15,
+            hide_subtasks: false,
+            sorting: Sorting::default(),
+            grouping: Grouping::default(),
+            view: TaskView::default(),
+            calendar_range: CalendarRange::default(),
         }
     }
 }
 
+/// How the tasks are laid out.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum TaskView {
+    /// A grid of cards with all details of the task.
+    #[default]
+    Cards,
+    /// A compact table with one row per task.
+    List,
+    /// A week or month calendar with the tasks on their due dates.
+    Calendar,
+}
+
+impl TaskView {
+    const ALL: [TaskView; 3] = [TaskView::Cards, TaskView::List, TaskView::Calendar];
+
+    fn caption(&self) -> String {
+        match self {
+            TaskView::Cards => format!("{} Cards", egui_phosphor::regular::SQUARES_FOUR),
+            TaskView::List => format!("{} List", egui_phosphor::regular::LIST),
+            TaskView::Calendar => format!("{} Calendar", egui_phosphor::regular::CALENDAR_DOTS),
+        }
+    }
+}
+
+/// State of the background search for calendars of a CalDAV account.
+#[derive(Default)]
+enum CalendarDiscovery {
+    #[default]
+    Idle,
+    Running,
+    Finished(Vec<(String, String)>),
+    Failed(String),
+}
+
+/// State of the background query for the work package types of an
+/// OpenProject server.
+#[derive(Default)]
+enum TypeDiscovery {
+    #[default]
+    Idle,
+    Running,
+    Finished(BTreeMap<u64, String>),
+    Failed(String),
+}
+
+/// State of the OAuth device flow login in the source dialog.
+#[derive(Default)]
+enum DeviceLogin {
+    #[default]
+    Idle,
+    Starting,
+    Waiting {
+        user_code: String,
+        verification_uri: String,
+    },
+    Finished(String),
+    LoggedIn,
+    Failed(String),
+}
+
+/// How long a removed source can be restored.
+const UNDO_REMOVE_DURATION: Duration = Duration::from_secs(10);
+
+/// A source that has been removed recently and can still be restored.
+struct RemovedSource {
+    source: TaskSource,
+    enabled: bool,
+    secret: Option<String>,
+    removed_at: Instant,
+}
+
+/// Values edited in the secret storage window.
+#[derive(Default)]
+struct SecretSettings {
+    command: String,
+    passphrase: String,
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct TaskPickerApp {
     task_manager: TaskManager,
     selected_task: Option<String>,
     settings: Settings,
+    filter: TaskFilter,
+    views: Vec<SavedView>,
+    #[serde(skip)]
+    new_view_name: String,
+    /// Number of weeks or months the calendar is moved from the current date.
+    #[serde(skip)]
+    calendar_offset: i32,
     #[serde(skip)]
     last_refreshed: Instant,
     #[serde(skip)]
@@ -51,9 +176,17 @@ pub struct TaskPickerApp {
     #[serde(skip)]
     currently_edited_secret: String,
     #[serde(skip)]
-    existing_edit_source: bool,
+    calendar_discovery: Arc<Mutex<CalendarDiscovery>>,
     #[serde(skip)]
-    connection_error_for_source: HashSet<String>,
+    type_discovery: Arc<Mutex<TypeDiscovery>>,
+    #[serde(skip)]
+    device_login: Arc<Mutex<DeviceLogin>>,
+    #[serde(skip)]
+    removed_sources: Vec<RemovedSource>,
+    #[serde(skip)]
+    secret_settings: Option<SecretSettings>,
+    #[serde(skip)]
+    connection_error_for_source: HashSet<Uuid>,
     #[serde(skip)]
     overwrite_current_time: Option<DateTime<Utc>>,
     #[serde(skip)]
@@ -70,10 +203,18 @@ impl Default for TaskPickerApp {
                 .checked_sub(Duration::from_secs(settings.refresh_rate_seconds))
                 .unwrap_or_else(Instant::now),
             settings,
+            filter: TaskFilter::default(),
+            views: Vec::default(),
+            new_view_name: String::default(),
+            calendar_offset: 0,
             edit_source: None,
             currently_edited_secret: String::default(),
             messages: Toasts::default(),
-            existing_edit_source: false,
+            calendar_discovery: Arc::default(),
+            type_discovery: Arc::default(),
+            device_login: Arc::default(),
+            removed_sources: Vec::default(),
+            secret_settings: None,
             connection_error_for_source: HashSet::default(),
             overwrite_current_time: None,
             app_version: env!("CARGO_PKG_VERSION").to_string(),
@@ -89,12 +230,26 @@ impl TaskPickerApp {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        let app = if let Some(storage) = cc.storage {
+        let mut app: TaskPickerApp = if let Some(storage) = cc.storage {
             eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
         } else {
             TaskPickerApp::default()
         };
 
+        // Fallback for systems without keyring
+        app.task_manager
+            .secrets()
+            .set_file(eframe::storage_dir("taskpicker").map(|d| d.join("secrets.json")));
+        app.task_manager.cleanup_orphaned_secrets();
+        app.task_manager.migrate_legacy_settings();
+        let source_ids: HashSet<Uuid> = app
+            .task_manager
+            .sources()
+            .iter()
+            .map(|(s, _)| s.id())
+            .collect();
+        app.reset_source_filters(|id| !source_ids.contains(&id));
+
         app.init_with_egui_context(&cc.egui_ctx);
 
         app
@@ -106,6 +261,126 @@ impl TaskPickerApp {
         ctx.set_fonts(fonts);
     }
 
+    /// Remove the given sources from the source filter and the saved views.
+    fn reset_source_filters<F>(&mut self, is_removed: F)
+    where
+        F: Fn(Uuid) -> bool,
+    {
+        let filters = std::iter::once(&mut self.filter)
+            .chain(self.views.iter_mut().map(|view| &mut view.filter));
+        for filter in filters {
+            if filter.source.is_some_and(&is_removed) {
+                filter.source = None;
+            }
+        }
+    }
+
+    /// Offer to restore recently removed sources for a short time.
+    fn show_undo_remove(&mut self, ctx: &egui::Context) {
+        let (expired, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.removed_sources)
+            .into_iter()
+            .partition(|removed| removed.removed_at.elapsed() > UNDO_REMOVE_DURATION);
+        self.removed_sources = pending;
+        if !expired.is_empty() {
+            // The removal can't be undone anymore
+            self.reset_source_filters(|id| expired.iter().any(|r| r.source.id() == id));
+        }
+        if self.removed_sources.is_empty() {
+            return;
+        }
+        let mut undo = None;
+        egui::Area::new(egui::Id::new("undo_remove_source"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -10.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for (i, removed) in self.removed_sources.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Removed source \"{}\"", removed.source.name()));
+                            if ui.button("Undo").clicked() {
+                                undo = Some(i);
+                            }
+                        });
+                    }
+                });
+            });
+        if let Some(i) = undo {
+            let removed = self.removed_sources.remove(i);
+            self.task_manager.restore_source(
+                removed.source,
+                removed.enabled,
+                &removed.secret.unwrap_or_default(),
+            );
+            self.trigger_refresh(true, ctx.clone());
+        }
+    }
+
+    fn edit_secret_settings(&mut self, ctx: &egui::Context) {
+        let Some(settings) = &mut self.secret_settings else {
+            return;
+        };
+        let mut close = false;
+        egui::Window::new("Secrets").show(ctx, |ui| {
+            ui.label(format!(
+                "Secrets can be given as environment variable named after the source, e.g. {}.",
+                secret_env_var("GitHub")
+            ));
+            ui.horizontal(|ui| {
+                ui.label("Secret command");
+                ui.add(
+                    TextEdit::singleline(&mut settings.command)
+                        .hint_text("pass show task-picker/{name}"),
+                );
+            });
+            ui.separator();
+            ui.label(
+                "Without a keyring, secrets are stored in a file encrypted with a master passphrase.",
+            );
+            ui.horizontal(|ui| {
+                ui.label("Master passphrase");
+                ui.add(TextEdit::singleline(&mut settings.passphrase).password(true));
+                let secrets = self.task_manager.secrets();
+                let label = if secrets.is_locked() { "Unlock" } else { "Set" };
+                if ui
+                    .add_enabled(!settings.passphrase.is_empty(), egui::Button::new(label))
+                    .clicked()
+                {
+                    match secrets.unlock(&settings.passphrase) {
+                        Ok(()) => {
+                            settings.passphrase.clear();
+                            self.messages.info("Secret file unlocked");
+                        }
+                        Err(e) => {
+                            self.messages.error(e.to_string());
+                        }
+                    }
+                }
+                if secrets.is_unlocked()
+                    && ui
+                        .button(egui_phosphor::regular::LOCK_OPEN)
+                        .on_hover_text("Lock the secret file")
+                        .clicked()
+                {
+                    secrets.lock();
+                    self.messages.info("Secret file locked");
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.task_manager
+                        .set_secret_command(settings.command.trim().to_string());
+                    close = true;
+                }
+                if ui.button("Discard").clicked() {
+                    close = true;
+                }
+            });
+        });
+        if close {
+            self.secret_settings = None;
+            self.trigger_refresh(true, ctx.clone());
+        }
+    }
+
     fn edit_source(&mut self, ctx: &egui::Context) {
         let window_title = if let Some(source) = &self.edit_source {
             format!("{} source", source.type_name())
@@ -117,12 +392,8 @@ impl TaskPickerApp {
                 match source {
                     TaskSource::CalDav(source) => {
                         ui.horizontal(|ui| {
-                            ui.label("Calendar Name");
-                            if self.existing_edit_source {
-                                ui.label(&source.calendar_name);
-                            } else {
-                                ui.text_edit_singleline(&mut source.calendar_name);
-                            }
+                            ui.label("Name");
+                            ui.text_edit_singleline(&mut source.name);
                         });
                         ui.horizontal(|ui| {
                             ui.label("Base Url");
@@ -140,15 +411,31 @@ impl TaskPickerApp {
                                     .password(true),
                             );
                         });
+                        ui.horizontal(|ui| {
+                            let mut limit_due_date = source.due_within_days.is_some();
+                            if ui
+                                .checkbox(&mut limit_due_date, "Only todos due within")
+                                .on_hover_text("Todos without due date are always included")
+                                .changed()
+                            {
+                                source.due_within_days = limit_due_date.then_some(7);
+                            }
+                            if let Some(days) = &mut source.due_within_days {
+                                ui.add(egui::DragValue::new(days).range(1..=365).suffix(" days"));
+                            }
+                        });
+                        ui.separator();
+                        Self::edit_calendar_selection(
+                            ui,
+                            source,
+                            &self.currently_edited_secret,
+                            &self.calendar_discovery,
+                        );
                     }
                     TaskSource::GitHub(source) => {
                         ui.horizontal(|ui| {
                             ui.label("Name");
-                            if self.existing_edit_source {
-                                ui.label(&source.name);
-                            } else {
-                                ui.text_edit_singleline(&mut source.name);
-                            }
+                            ui.text_edit_singleline(&mut source.name);
                         });
                         ui.horizontal(|ui| {
                             ui.label("Server URL");
@@ -161,24 +448,29 @@ impl TaskPickerApp {
                                     .password(true),
                             );
                         });
+                        ui.separator();
+                        Self::edit_github_query(ui, source);
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label("OAuth Client ID");
+                            ui.text_edit_singleline(&mut source.oauth_client_id);
+                        });
+                        Self::edit_device_login(
+                            ui,
+                            source.device_flow(),
+                            &mut self.currently_edited_secret,
+                            &self.device_login,
+                        );
                     }
                     TaskSource::GitLab(source) => {
                         ui.horizontal(|ui| {
                             ui.label("Name");
-                            if self.existing_edit_source {
-                                ui.label(&source.name);
-                            } else {
-                                ui.text_edit_singleline(&mut source.name);
-                            }
+                            ui.text_edit_singleline(&mut source.name);
                         });
                         ui.horizontal(|ui| {
                             ui.label("Server URL");
                             ui.text_edit_singleline(&mut source.server_url);
                         });
-                        ui.horizontal(|ui| {
-                            ui.label("User ID");
-                            ui.text_edit_singleline(&mut source.user_name);
-                        });
                         ui.horizontal(|ui| {
                             ui.label("API Token");
                             ui.add(
@@ -186,15 +478,45 @@ impl TaskPickerApp {
                                     .password(true),
                             );
                         });
+                        ui.separator();
+                        ui.checkbox(&mut source.todos, "Todos");
+                        ui.checkbox(&mut source.assigned_issues, "Assigned issues");
+                        ui.checkbox(
+                            &mut source.assigned_merge_requests,
+                            "Assigned merge requests",
+                        );
+                        ui.horizontal(|ui| {
+                            ui.checkbox(
+                                &mut source.review_merge_requests,
+                                "Merge requests to review by",
+                            );
+                            ui.add_enabled(
+                                source.review_merge_requests,
+                                TextEdit::singleline(&mut source.user_name)
+                                    .hint_text("Owner of the token"),
+                            )
+                            .on_hover_text("User name or ID");
+                        });
+                        ui.checkbox(
+                            &mut source.hide_closed_todos,
+                            "Hide todos of closed issues and merged merge requests",
+                        );
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label("OAuth Client ID");
+                            ui.text_edit_singleline(&mut source.oauth_client_id);
+                        });
+                        Self::edit_device_login(
+                            ui,
+                            source.device_flow(),
+                            &mut self.currently_edited_secret,
+                            &self.device_login,
+                        );
                     }
                     TaskSource::OpenProject(source) => {
                         ui.horizontal(|ui| {
                             ui.label("Name");
-                            if self.existing_edit_source {
-                                ui.label(&source.name);
-                            } else {
-                                ui.text_edit_singleline(&mut source.name);
-                            }
+                            ui.text_edit_singleline(&mut source.name);
                         });
                         ui.horizontal(|ui| {
                             ui.label("Server URL");
@@ -208,6 +530,24 @@ impl TaskPickerApp {
                                     .password(true),
                             );
                         });
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            let mut use_query = source.query_id.is_some();
+                            if ui.checkbox(&mut use_query, "Saved query").changed() {
+                                source.query_id = use_query.then_some(1);
+                            }
+                            if let Some(query_id) = &mut source.query_id {
+                                ui.add(egui::DragValue::new(query_id).range(1..=u64::MAX));
+                            }
+                        });
+                        if source.query_id.is_none() {
+                            Self::edit_type_selection(
+                                ui,
+                                source,
+                                &self.currently_edited_secret,
+                                &self.type_discovery,
+                            );
+                        }
                     }
                 }
                 ui.horizontal(|ui| {
@@ -220,20 +560,321 @@ impl TaskPickerApp {
                         }
                         self.edit_source = None;
                         self.currently_edited_secret.clear();
+                        *self.calendar_discovery.lock().expect("Lock poisoning") =
+                            CalendarDiscovery::Idle;
+                        *self.type_discovery.lock().expect("Lock poisoning") = TypeDiscovery::Idle;
+                        *self.device_login.lock().expect("Lock poisoning") = DeviceLogin::Idle;
                         self.trigger_refresh(true, ctx.clone());
                     }
                     if ui.button("Discard").clicked() {
                         self.edit_source = None;
                         self.currently_edited_secret.clear();
+                        *self.calendar_discovery.lock().expect("Lock poisoning") =
+                            CalendarDiscovery::Idle;
+                        *self.type_discovery.lock().expect("Lock poisoning") = TypeDiscovery::Idle;
+                        *self.device_login.lock().expect("Lock poisoning") = DeviceLogin::Idle;
                     }
                 });
             }
         });
     }
 
-    fn render_single_task(&mut self, ui: &mut Ui, task: Task, now: DateTime<Utc>) {
+    fn edit_github_query(ui: &mut Ui, source: &mut GitHubSource) {
+        ui.horizontal(|ui| {
+            ui.label("Search query");
+            ui.add(
+                TextEdit::singleline(&mut source.search_query)
+                    .hint_text("is:open review-requested:@me"),
+            );
+        });
+        // The search query replaces the other options
+        let use_filter = source.search_query.trim().is_empty();
+        ui.add_enabled_ui(use_filter, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Issues");
+                egui::ComboBox::from_id_salt("github_issue_filter")
+                    .selected_text(source.filter.name())
+                    .show_ui(ui, |ui| {
+                        for filter in IssueFilter::ALL {
+                            ui.selectable_value(&mut source.filter, filter, filter.name());
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Labels");
+                ui.add(TextEdit::singleline(&mut source.labels).hint_text("bug, help wanted"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Organizations");
+                ui.add(
+                    TextEdit::singleline(&mut source.organizations)
+                        .hint_text("Comma separated, all if empty"),
+                );
+            });
+        });
+        egui::CollapsingHeader::new("Project").show(ui, |ui| {
+            let project = &mut source.project;
+            ui.label("Take due dates and status from the fields of a project.");
+            egui::Grid::new("github_project").show(ui, |ui| {
+                ui.label("Owner");
+                ui.add(TextEdit::singleline(&mut project.owner).hint_text("Organization or user"));
+                ui.end_row();
+                ui.label("Number");
+                ui.add(egui::DragValue::new(&mut project.number));
+                ui.end_row();
+                ui.label("Due date field");
+                ui.text_edit_singleline(&mut project.due_field);
+                ui.end_row();
+                ui.label("Iteration field");
+                ui.text_edit_singleline(&mut project.iteration_field);
+                ui.end_row();
+                ui.label("Status field");
+                ui.text_edit_singleline(&mut project.status_field);
+                ui.end_row();
+                ui.label("Hide status");
+                ui.text_edit_singleline(&mut project.done_status);
+                ui.end_row();
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut source.review_requests, "Pull requests to review");
+            ui.add_enabled(
+                source.review_requests,
+                egui::Checkbox::new(&mut source.exclude_draft_reviews, "Exclude drafts"),
+            );
+        });
+    }
+
+    /// Log in with the OAuth device flow and replace the secret with the
+    /// obtained token.
+    fn edit_device_login(
+        ui: &mut Ui,
+        flow: DeviceFlow,
+        secret: &mut String,
+        login: &Arc<Mutex<DeviceLogin>>,
+    ) {
+        let mut login_state = login.lock().expect("Lock poisoning");
+        if let DeviceLogin::Finished(token) = &*login_state {
+            *secret = token.clone();
+            *login_state = DeviceLogin::LoggedIn;
+        }
+        ui.horizontal(|ui| {
+            let running = matches!(
+                *login_state,
+                DeviceLogin::Starting | DeviceLogin::Waiting { .. }
+            );
+            if ui
+                .add_enabled(
+                    !running && flow.has_client_id(),
+                    egui::Button::new("Log in with browser"),
+                )
+                .on_disabled_hover_text("Requires the client ID of an OAuth application")
+                .clicked()
+            {
+                *login_state = DeviceLogin::Starting;
+                let login = login.clone();
+                let ctx = ui.ctx().clone();
+                rayon::spawn(move || {
+                    let result = flow.start().and_then(|authorization| {
+                        *login.lock().expect("Lock poisoning") = DeviceLogin::Waiting {
+                            user_code: authorization.user_code.clone(),
+                            verification_uri: authorization.verification_uri.clone(),
+                        };
+                        ctx.request_repaint();
+                        // Stop polling when the dialog has been closed
+                        flow.wait_for_token(&authorization, || {
+                            !matches!(
+                                &*login.lock().expect("Lock poisoning"),
+                                DeviceLogin::Waiting { user_code, .. }
+                                    if *user_code == authorization.user_code
+                            )
+                        })
+                    });
+                    let mut login = login.lock().expect("Lock poisoning");
+                    match result {
+                        Ok(token) => *login = DeviceLogin::Finished(token.to_secret()),
+                        Err(e) => {
+                            if matches!(*login, DeviceLogin::Starting | DeviceLogin::Waiting { .. })
+                            {
+                                *login = DeviceLogin::Failed(e.to_string());
+                            }
+                        }
+                    }
+                    ctx.request_repaint();
+                });
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+        match &*login_state {
+            DeviceLogin::Waiting {
+                user_code,
+                verification_uri,
+            } => {
+                ui.horizontal(|ui| {
+                    ui.label("Enter the code");
+                    ui.label(RichText::new(user_code).monospace().strong());
+                    if ui.small_button(egui_phosphor::regular::COPY).clicked() {
+                        ui.ctx().copy_text(user_code.clone());
+                    }
+                    ui.label("at");
+                    ui.hyperlink(verification_uri);
+                });
+            }
+            DeviceLogin::LoggedIn => {
+                ui.label("Logged in. Save the source to keep the token.");
+            }
+            DeviceLogin::Failed(msg) => {
+                ui.colored_label(ui.visuals().error_fg_color, msg);
+            }
+            DeviceLogin::Idle | DeviceLogin::Starting | DeviceLogin::Finished(_) => {}
+        }
+    }
+
+    fn edit_calendar_selection(
+        ui: &mut Ui,
+        source: &mut CalDavSource,
+        secret: &str,
+        discovery: &Arc<Mutex<CalendarDiscovery>>,
+    ) {
+        let mut discovery_state = discovery.lock().expect("Lock poisoning");
+        ui.horizontal(|ui| {
+            ui.label("Calendars");
+            let running = matches!(*discovery_state, CalendarDiscovery::Running);
+            if ui
+                .add_enabled(!running, egui::Button::new("Discover"))
+                .clicked()
+            {
+                *discovery_state = CalendarDiscovery::Running;
+                let source = source.clone();
+                let secret = secret.to_string();
+                let discovery = discovery.clone();
+                let ctx = ui.ctx().clone();
+                rayon::spawn(move || {
+                    let result = match source.discover_calendars(Some(secret)) {
+                        Ok(calendars) => CalendarDiscovery::Finished(calendars),
+                        Err(e) => CalendarDiscovery::Failed(e.to_string()),
+                    };
+                    *discovery.lock().expect("Lock poisoning") = result;
+                    ctx.request_repaint();
+                });
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+
+        // Show the discovered calendars and the already selected ones, which
+        // might not be available any longer.
+        let mut calendars = source.calendars.clone();
+        match &*discovery_state {
+            CalendarDiscovery::Finished(discovered) => {
+                source.resolve_calendar_name(discovered);
+                calendars.extend(discovered.iter().cloned());
+            }
+            CalendarDiscovery::Failed(msg) => {
+                ui.colored_label(ui.visuals().error_fg_color, msg);
+            }
+            CalendarDiscovery::Idle | CalendarDiscovery::Running => {}
+        }
+        if let Some(name) = &source.unresolved_calendar {
+            ui.weak(format!("The calendar \"{name}\" is included."));
+        } else if source.calendars.is_empty() {
+            ui.weak("No calendar selected, all calendars are included.");
+        }
+        for (url, name) in calendars {
+            let mut selected = source.calendars.contains_key(&url);
+            if ui
+                .checkbox(&mut selected, &name)
+                .on_hover_text(&url)
+                .changed()
+            {
+                if selected {
+                    source.calendars.insert(url, name);
+                } else {
+                    source.calendars.remove(&url);
+                }
+            }
+        }
+    }
+
+    fn edit_type_selection(
+        ui: &mut Ui,
+        source: &mut OpenProjectSource,
+        secret: &str,
+        discovery: &Arc<Mutex<TypeDiscovery>>,
+    ) {
+        let mut discovery_state = discovery.lock().expect("Lock poisoning");
+        ui.horizontal(|ui| {
+            ui.label("Types");
+            let running = matches!(*discovery_state, TypeDiscovery::Running);
+            if ui
+                .add_enabled(!running, egui::Button::new("Load types"))
+                .clicked()
+            {
+                *discovery_state = TypeDiscovery::Running;
+                let source = source.clone();
+                let secret = secret.to_string();
+                let discovery = discovery.clone();
+                let ctx = ui.ctx().clone();
+                rayon::spawn(move || {
+                    let result = match source.discover_types(Some(secret)) {
+                        Ok(types) => TypeDiscovery::Finished(types),
+                        Err(e) => TypeDiscovery::Failed(e.to_string()),
+                    };
+                    *discovery.lock().expect("Lock poisoning") = result;
+                    ctx.request_repaint();
+                });
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+
+        // Show the types of the server and the already selected ones
+        let mut types = source.types.clone();
+        match &*discovery_state {
+            TypeDiscovery::Finished(discovered) => types.extend(discovered.clone()),
+            TypeDiscovery::Failed(msg) => {
+                ui.colored_label(ui.visuals().error_fg_color, msg);
+            }
+            TypeDiscovery::Idle | TypeDiscovery::Running => {}
+        }
+        if source.types.is_empty() {
+            ui.label("All types are included.");
+        }
+        for (id, name) in types {
+            let mut selected = source.types.contains_key(&id);
+            if ui.checkbox(&mut selected, &name).changed() {
+                if selected {
+                    source.types.insert(id, name);
+                } else {
+                    source.types.remove(&id);
+                }
+            }
+        }
+    }
+
+    fn toggle_selection(&mut self, task: &Task) {
+        if Some(task.get_id()) == self.selected_task {
+            // Already selected, deselect
+            self.selected_task = None;
+        } else {
+            // Select this task
+            self.selected_task = Some(task.get_id());
+        }
+    }
+
+    fn render_single_task(
+        &mut self,
+        ui: &mut Ui,
+        task: Task,
+        subtasks: &[Task],
+        now: DateTime<Utc>,
+    ) {
         let mut group = egui::Frame::group(ui.style());
-        let overdue = task.due.filter(|d| d.cmp(&now).is_le()).is_some();
+        let overdue = is_overdue(&task, now);
         if Some(task.get_id()) == self.selected_task {
             group.fill = ui.visuals().selection.bg_fill;
         } else if overdue {
@@ -255,13 +896,7 @@ impl TaskPickerApp {
                         "Select"
                     };
                     if ui.button(caption).clicked() {
-                        if Some(task.get_id()) == self.selected_task {
-                            // Already selected, deselect
-                            self.selected_task = None;
-                        } else {
-                            // Select this task
-                            self.selected_task = Some(task.get_id());
-                        }
+                        self.toggle_selection(&task);
                     }
                 });
                 if !task_is_selected {
@@ -273,39 +908,88 @@ impl TaskPickerApp {
                     }
                 }
                 ui.heading(task.title.as_str().truncate_ellipse(80));
-                ui.label(egui::RichText::new(task.project));
+                let project = if let Some(source_kind) = task.source_kind {
+                    format!("{} {}", source_kind.icon(), task.project)
+                } else {
+                    task.project.clone()
+                };
+                let project_label = ui.label(egui::RichText::new(project));
+                if !task.source_name.is_empty() {
+                    project_label.on_hover_text(format!("Source: {}", task.source_name));
+                }
+                render_badges(ui, &task);
+                if let Some(reason) = &task.reason {
+                    ui.label(format!(
+                        "{} {}",
+                        egui_phosphor::regular::BELL,
+                        reason_text(reason).as_str().truncate_ellipse(60)
+                    ));
+                }
+                if let Some(percent_complete) = task.percent_complete {
+                    ui.add(
+                        egui::ProgressBar::new(f32::from(percent_complete.min(100)) / 100.0)
+                            .desired_height(8.0)
+                            .text(RichText::new(format!("{percent_complete}%")).small()),
+                    );
+                }
 
                 if let Some(due_utc) = &task.due {
                     // Convert to local time for display
                     let due_local: DateTime<Local> = due_utc.with_timezone(&Local);
                     let mut due_label =
                         RichText::new(format!("Due: {}", due_local.format("%a, %d %b %Y %H:%M")));
-                    if !overdue {
-                        // Mark the number of days with the color.
-                        // If the task is overdue, the background
-                        // already be red, an no further highlight
-                        // is necessary.
-                        let hours_to_finish = due_utc.signed_duration_since(now).num_hours();
-                        if hours_to_finish < 24 {
-                            due_label = due_label.color(ui.visuals().error_fg_color);
-                        } else if hours_to_finish < 48 {
-                            due_label = due_label.color(ui.visuals().warn_fg_color);
-                        };
+                    if let Some(color) = due_soon_color(ui, *due_utc, now) {
+                        due_label = due_label.color(color);
                     }
                     ui.label(due_label);
                 }
                 if let Some(created) = &task.created {
                     ui.label(format!("Created: {}", created.format("%a, %d %b %Y %H:%M")));
                 }
+                if let Some(author) = &task.author {
+                    ui.label(format!("Author: {}", author.as_str().truncate_ellipse(40)));
+                }
+                if !task.assignees.is_empty() {
+                    ui.label(format!(
+                        "{} {}",
+                        egui_phosphor::regular::USER,
+                        task.assignees.join(", ").as_str().truncate_ellipse(40)
+                    ));
+                }
+                if let Some(location) = &task.location {
+                    ui.label(format!(
+                        "{} {}",
+                        egui_phosphor::regular::MAP_PIN,
+                        location.as_str().truncate_ellipse(40)
+                    ));
+                }
+                if !subtasks.is_empty() {
+                    egui::CollapsingHeader::new(format!("Subtasks ({})", subtasks.len()))
+                        .id_salt(("subtasks", task.get_id()))
+                        .show(ui, |ui| {
+                            ScrollArea::vertical().max_height(80.0).show(ui, |ui| {
+                                for subtask in subtasks {
+                                    ui.label(format!(
+                                        "{} {}",
+                                        egui_phosphor::regular::SQUARE,
+                                        subtask.title.as_str().truncate_ellipse(40)
+                                    ));
+                                }
+                            });
+                        });
+                }
                 ui.separator();
-                if task.description.starts_with("https://") {
+                if let Some(url) = &task.url {
                     ui.hyperlink_to(
-                        task.description.as_str().truncate_ellipse(100),
-                        task.description.as_str(),
+                        format!(
+                            "{} {}",
+                            egui_phosphor::regular::LINK,
+                            url.as_str().truncate_ellipse(60)
+                        ),
+                        url,
                     );
-                } else {
-                    ui.label(task.description.as_str().truncate_ellipse(100));
                 }
+                ui.label(description_layout(ui, &task));
             });
         });
     }
@@ -325,14 +1009,474 @@ impl TaskPickerApp {
             }
         }
 
+        // Collect the open subtasks for each task
+        let mut subtasks: HashMap<String, Vec<Task>> = HashMap::default();
+        for task in &all_tasks {
+            if let Some(parent) = task.get_parent_id() {
+                subtasks.entry(parent).or_default().push(task.clone());
+            }
+        }
+        let task_ids: HashSet<String> = all_tasks.iter().map(|t| t.get_id()).collect();
+        // Subtasks are shown as part of their parent task, if the parent
+        // task is still open
+        let hide_subtasks = self.settings.hide_subtasks;
+        let visible_tasks: Vec<Task> = all_tasks
+            .into_iter()
+            .filter(|task| {
+                !hide_subtasks
+                    || !task
+                        .get_parent_id()
+                        .is_some_and(|parent| task_ids.contains(&parent))
+            })
+            .collect();
+
+        if self.settings.view == TaskView::Calendar {
+            // The calendar is already arranged by date and is not grouped
+            self.render_calendar(ui, visible_tasks, now);
+            return;
+        }
+        if self.settings.view == TaskView::List {
+            Self::render_list_header(ui, &mut self.settings.sorting);
+        }
+        if self.settings.grouping == Grouping::None {
+            self.render_tasks(ui, "task-grid", visible_tasks, &subtasks, columns, now);
+            return;
+        }
+        let source_order: Vec<Uuid> = self
+            .task_manager
+            .sources()
+            .iter()
+            .map(|(s, _)| s.id())
+            .collect();
+        for group in group_tasks(visible_tasks, self.settings.grouping, &source_order, now) {
+            let name = if group.name.is_empty() {
+                "Other"
+            } else {
+                group.name.as_str()
+            };
+            egui::CollapsingHeader::new(
+                RichText::new(format!("{name} ({})", group.tasks.len())).heading(),
+            )
+            .id_salt(("task-group", &group.name))
+            .default_open(true)
+            .show(ui, |ui| {
+                self.render_tasks(
+                    ui,
+                    ("task-grid", &group.name),
+                    group.tasks,
+                    &subtasks,
+                    columns,
+                    now,
+                );
+            });
+        }
+    }
+
+    fn render_tasks(
+        &mut self,
+        ui: &mut Ui,
+        id_salt: impl std::hash::Hash,
+        tasks: Vec<Task>,
+        subtasks: &HashMap<String, Vec<Task>>,
+        columns: usize,
+        now: DateTime<Utc>,
+    ) {
+        match self.settings.view {
+            TaskView::Cards => self.render_task_grid(ui, id_salt, tasks, subtasks, columns, now),
+            TaskView::List => self.render_task_list(ui, tasks, subtasks, now),
+            TaskView::Calendar => self.render_calendar(ui, tasks, now),
+        }
+    }
+
+    /// Column titles of the list view. Clicking on a title sorts by this
+    /// column first.
+    fn render_list_header(ui: &mut Ui, sorting: &mut Sorting) {
+        let title_width = list_title_width(ui);
+        ui.horizontal(|ui| {
+            let columns = [
+                ("", ICON_COLUMN_WIDTH, SortKey::Source),
+                ("Title", title_width, SortKey::Title),
+                ("Project", PROJECT_COLUMN_WIDTH, SortKey::Project),
+                ("Due", DUE_COLUMN_WIDTH, SortKey::Due),
+                ("Age", AGE_COLUMN_WIDTH, SortKey::Created),
+            ];
+            for (name, width, key) in columns {
+                list_cell(ui, width, |ui| {
+                    let sorted = sorting.keys.first() == Some(&key);
+                    let caption = if sorted {
+                        format!("{name} {}", egui_phosphor::regular::CARET_DOWN)
+                    } else {
+                        name.to_string()
+                    };
+                    let response = ui
+                        .selectable_label(sorted, RichText::new(caption).strong())
+                        .on_hover_text(format!("Sort by {}", key.name().to_lowercase()));
+                    if response.clicked() {
+                        sorting.sort_first_by(key);
+                    }
+                });
+            }
+        });
+    }
+
+    fn render_task_list(
+        &mut self,
+        ui: &mut Ui,
+        tasks: Vec<Task>,
+        subtasks: &HashMap<String, Vec<Task>>,
+        now: DateTime<Utc>,
+    ) {
+        let title_width = list_title_width(ui);
+        for (i, task) in tasks.into_iter().enumerate() {
+            let subtask_count = subtasks.get(&task.get_id()).map_or(0, |s| s.len());
+            self.render_task_row(ui, &task, subtask_count, i % 2 == 1, title_width, now);
+        }
+    }
+
+    fn render_task_row(
+        &mut self,
+        ui: &mut Ui,
+        task: &Task,
+        subtask_count: usize,
+        striped: bool,
+        title_width: f32,
+        now: DateTime<Utc>,
+    ) {
+        let task_is_selected = Some(task.get_id()) == self.selected_task;
+        let overdue = is_overdue(task, now);
+        let mut row = egui::Frame::new().inner_margin(egui::Margin::symmetric(4, 0));
+        if task_is_selected {
+            row.fill = ui.visuals().selection.bg_fill;
+        } else if overdue {
+            row.fill = ui.visuals().error_fg_color;
+        } else if striped {
+            row.fill = ui.visuals().faint_bg_color;
+        }
+        row.show(ui, |ui| {
+            if !task_is_selected {
+                if overdue {
+                    ui.visuals_mut().override_text_color = Some(Color32::WHITE);
+                } else if self.selected_task.is_some() {
+                    // Another task is selected. Make this task less visible
+                    ui.visuals_mut().override_text_color = Some(ui.visuals().weak_text_color());
+                }
+            }
+            ui.horizontal(|ui| {
+                list_cell(ui, ICON_COLUMN_WIDTH, |ui| {
+                    if let Some(source_kind) = task.source_kind {
+                        let icon = ui.label(source_kind.icon());
+                        if !task.source_name.is_empty() {
+                            icon.on_hover_text(format!("Source: {}", task.source_name));
+                        }
+                    }
+                });
+                list_cell(ui, title_width, |ui| {
+                    let title = if subtask_count > 0 {
+                        format!("{} (+{subtask_count})", task.title)
+                    } else {
+                        task.title.clone()
+                    };
+                    let mut hover_text = task.title.clone();
+                    if let Some(reason) = &task.reason {
+                        hover_text = format!("{hover_text}\n{}", reason_text(reason));
+                    }
+                    let response = ui
+                        .add(
+                            egui::Label::new(title)
+                                .truncate()
+                                .sense(egui::Sense::click()),
+                        )
+                        .on_hover_text(hover_text);
+                    if response.clicked() {
+                        self.toggle_selection(task);
+                    }
+                });
+                list_cell(ui, PROJECT_COLUMN_WIDTH, |ui| {
+                    ui.add(egui::Label::new(task.project.as_str()).truncate());
+                });
+                list_cell(ui, DUE_COLUMN_WIDTH, |ui| {
+                    if let Some(due_utc) = task.due {
+                        let due_local: DateTime<Local> = due_utc.with_timezone(&Local);
+                        let mut due_label =
+                            RichText::new(due_local.format("%a, %d %b %H:%M").to_string());
+                        if let Some(color) = due_soon_color(ui, due_utc, now) {
+                            due_label = due_label.color(color);
+                        }
+                        ui.label(due_label);
+                    }
+                });
+                list_cell(ui, AGE_COLUMN_WIDTH, |ui| {
+                    if let Some(created) = task.created {
+                        ui.label(format_age(created, now)).on_hover_text(format!(
+                            "Created: {}",
+                            created.format("%a, %d %b %Y %H:%M")
+                        ));
+                    }
+                });
+                list_cell(ui, ICON_COLUMN_WIDTH, |ui| {
+                    if let Some(url) = &task.url {
+                        ui.hyperlink_to(egui_phosphor::regular::LINK, url)
+                            .on_hover_text(url);
+                    }
+                });
+            });
+        });
+    }
+
+    fn render_calendar(&mut self, ui: &mut Ui, tasks: Vec<Task>, now: DateTime<Utc>) {
+        let today = now.with_timezone(&Local).date_naive();
+        let page = ui
+            .horizontal(|ui| {
+                if ui
+                    .button(egui_phosphor::regular::CARET_LEFT)
+                    .on_hover_text("Previous")
+                    .clicked()
+                {
+                    self.calendar_offset -= 1;
+                }
+                if ui.button("Today").clicked() {
+                    self.calendar_offset = 0;
+                }
+                if ui
+                    .button(egui_phosphor::regular::CARET_RIGHT)
+                    .on_hover_text("Next")
+                    .clicked()
+                {
+                    self.calendar_offset += 1;
+                }
+                ui.separator();
+                for range in CalendarRange::ALL {
+                    if ui
+                        .selectable_value(&mut self.settings.calendar_range, range, range.name())
+                        .changed()
+                    {
+                        self.calendar_offset = 0;
+                    }
+                }
+                ui.separator();
+                let page =
+                    CalendarPage::new(self.settings.calendar_range, today, self.calendar_offset);
+                ui.strong(&page.title);
+                page
+            })
+            .inner;
+
+        let (dated, undated): (Vec<Task>, Vec<Task>) =
+            tasks.into_iter().partition(|t| t.due.is_some());
+        ui.horizontal_top(|ui| {
+            let calendar_width =
+                (ui.available_width() - TRAY_WIDTH - 4.0 * ui.spacing().item_spacing.x)
+                    .max(7.0 * 60.0);
+            ui.vertical(|ui| {
+                ui.set_width(calendar_width);
+                for week_index in 0..page.weeks.len() {
+                    self.render_calendar_week(ui, &page, week_index, &dated, today, now);
+                }
+            });
+            ui.separator();
+            ui.vertical(|ui| {
+                ui.set_width(TRAY_WIDTH);
+                ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
+                ui.strong(format!(
+                    "{} No due date ({})",
+                    egui_phosphor::regular::TRAY,
+                    undated.len()
+                ));
+                for task in &undated {
+                    let caption = match task.source_kind {
+                        Some(source_kind) => format!("{} {}", source_kind.icon(), task.title),
+                        None => task.title.clone(),
+                    };
+                    let task_is_selected = Some(task.get_id()) == self.selected_task;
+                    let response = ui
+                        .selectable_label(task_is_selected, caption)
+                        .on_hover_text(&task.title);
+                    if response.clicked() {
+                        self.toggle_selection(task);
+                    }
+                    open_link_menu(&response, task);
+                }
+            });
+        });
+    }
+
+    /// One week of the calendar with the days as columns. Tasks are drawn as
+    /// bars from their start to their due date.
+    fn render_calendar_week(
+        &mut self,
+        ui: &mut Ui,
+        page: &CalendarPage,
+        week_index: usize,
+        tasks: &[Task],
+        today: NaiveDate,
+        now: DateTime<Utc>,
+    ) {
+        let week = &page.weeks[week_index];
+        let width = ui.available_width();
+        let column_width = width / 7.0;
+        let lanes = week_lanes(tasks, week);
+        let background = ui.painter().add(egui::Shape::Noop);
+        let top = ui.cursor().top();
+
+        let header_height = ui.text_style_height(&egui::TextStyle::Body) + 4.0;
+        let (header, _) =
+            ui.allocate_exact_size(Vec2::new(width, header_height), egui::Sense::hover());
+        for (column, day) in week.iter().enumerate() {
+            let mut color = ui.visuals().text_color();
+            if !page.is_in_month(*day) {
+                color = ui.visuals().weak_text_color();
+            } else if *day == today {
+                color = ui.visuals().strong_text_color();
+            }
+            ui.painter().text(
+                egui::pos2(
+                    header.left() + column as f32 * column_width + 4.0,
+                    header.center().y,
+                ),
+                egui::Align2::LEFT_CENTER,
+                day.format("%a %d").to_string(),
+                FontSelection::Default.resolve(ui.style()),
+                color,
+            );
+        }
+
+        let bar_height = ui.spacing().interact_size.y;
+        // Keep some space for empty weeks, so the days are easy to see
+        let min_lanes = match page.month {
+            Some(_) => 2,
+            None => 5,
+        };
+        for lane_index in 0..lanes.len().max(min_lanes) {
+            let (lane, _) =
+                ui.allocate_exact_size(Vec2::new(width, bar_height), egui::Sense::hover());
+            for bar in lanes.get(lane_index).into_iter().flatten() {
+                let task = &tasks[bar.task];
+                let rect = egui::Rect::from_x_y_ranges(
+                    (lane.left() + bar.first as f32 * column_width + 2.0)
+                        ..=(lane.left() + (bar.last + 1) as f32 * column_width - 2.0),
+                    lane.y_range().shrink(1.0),
+                );
+                self.render_calendar_bar(ui, (week_index, lane_index), rect, bar, task, now);
+            }
+        }
+
+        // Draw the columns of the days behind the bars
+        let visuals = ui.visuals();
+        let bottom = ui.cursor().top();
+        let mut shapes = Vec::new();
+        for (column, day) in week.iter().enumerate() {
+            let left = header.left() + column as f32 * column_width;
+            let rect = egui::Rect::from_x_y_ranges(left..=left + column_width, top..=bottom);
+            if *day == today {
+                shapes.push(egui::Shape::rect_filled(
+                    rect,
+                    0.0,
+                    visuals.extreme_bg_color,
+                ));
+            }
+            shapes.push(egui::Shape::rect_stroke(
+                rect,
+                0.0,
+                visuals.widgets.noninteractive.bg_stroke,
+                egui::StrokeKind::Inside,
+            ));
+        }
+        ui.painter().set(background, shapes);
+    }
+
+    fn render_calendar_bar(
+        &mut self,
+        ui: &mut Ui,
+        id_salt: impl std::hash::Hash,
+        rect: egui::Rect,
+        bar: &CalendarBar,
+        task: &Task,
+        now: DateTime<Utc>,
+    ) {
+        let task_is_selected = Some(task.get_id()) == self.selected_task;
+        let visuals = ui.visuals();
+        let (fill, text_color) = if task_is_selected {
+            (visuals.selection.bg_fill, visuals.strong_text_color())
+        } else if is_overdue(task, now) {
+            (visuals.error_fg_color, Color32::WHITE)
+        } else if self.selected_task.is_some() {
+            // Another task is selected. Make this task less visible
+            (visuals.faint_bg_color, visuals.weak_text_color())
+        } else {
+            let due_color = task
+                .due
+                .and_then(|due| due_soon_color(ui, due, now))
+                .unwrap_or_else(|| visuals.text_color());
+            (visuals.widgets.inactive.bg_fill, due_color)
+        };
+        // Bars that continue in the previous or next week have straight ends
+        let round = |continues: bool| if continues { 0 } else { 4 };
+        let corner_radius = egui::CornerRadius {
+            nw: round(bar.continues_before),
+            sw: round(bar.continues_before),
+            ne: round(bar.continues_after),
+            se: round(bar.continues_after),
+        };
+        ui.painter().rect_filled(rect, corner_radius, fill);
+        let caption = match task.source_kind {
+            Some(source_kind) => format!("{} {}", source_kind.icon(), task.title),
+            None => task.title.clone(),
+        };
+        ui.painter().with_clip_rect(rect.shrink(2.0)).text(
+            rect.left_center() + Vec2::new(4.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            caption,
+            FontSelection::Default.resolve(ui.style()),
+            text_color,
+        );
+
+        let response = ui.interact(
+            rect,
+            ui.id().with(("calendar-bar", task.get_id(), id_salt)),
+            egui::Sense::click(),
+        );
+        response
+            .widget_info(|| egui::WidgetInfo::labeled(egui::WidgetType::Button, true, &task.title));
+        if response.clicked() {
+            self.toggle_selection(task);
+        }
+        open_link_menu(&response, task);
+        response.on_hover_ui(|ui| {
+            ui.strong(&task.title);
+            if !task.project.is_empty() {
+                ui.label(&task.project);
+            }
+            if let Some(start) = task.start {
+                let start: DateTime<Local> = start.with_timezone(&Local);
+                ui.label(format!("Start: {}", start.format("%a, %d %b %Y %H:%M")));
+            }
+            if let Some(due) = task.due {
+                let due: DateTime<Local> = due.with_timezone(&Local);
+                ui.label(format!("Due: {}", due.format("%a, %d %b %Y %H:%M")));
+            }
+        });
+    }
+
+    fn render_task_grid(
+        &mut self,
+        ui: &mut Ui,
+        id_salt: impl std::hash::Hash,
+        tasks: Vec<Task>,
+        subtasks: &HashMap<String, Vec<Task>>,
+        columns: usize,
+        now: DateTime<Utc>,
+    ) {
         // Create a grid layout where each row can show up to 5 tasks
-        egui::Grid::new("task-grid")
+        egui::Grid::new(id_salt)
             .num_columns(columns)
             .show(ui, |ui| {
-                // Get all tasks for all active source
                 let mut task_counter = 0;
-                for task in all_tasks {
-                    self.render_single_task(ui, task, now);
+                for task in tasks {
+                    let subtasks_of_task = subtasks
+                        .get(&task.get_id())
+                        .map(|s| s.as_slice())
+                        .unwrap_or_default();
+                    self.render_single_task(ui, task, subtasks_of_task, now);
                     task_counter += 1;
                     if task_counter % columns == 0 {
                         ui.end_row();
@@ -341,6 +1485,204 @@ impl TaskPickerApp {
             });
     }
 
+    fn render_filter_bar(
+        &mut self,
+        ui: &mut Ui,
+        all_tasks: &[Task],
+        shown: usize,
+        filter_error: Option<String>,
+    ) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label(egui_phosphor::regular::MAGNIFYING_GLASS);
+            ui.add(
+                TextEdit::singleline(&mut self.filter.search)
+                    .hint_text("Search or filter, e.g. label:bug due<7d")
+                    .desired_width(220.0),
+            )
+            .on_hover_text(FILTER_HELP);
+            if let Some(error) = filter_error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            let sources = self.task_manager.sources();
+            let selected_source = sources
+                .iter()
+                .find(|(s, _)| Some(s.id()) == self.filter.source)
+                .map(|(s, _)| s.name())
+                .unwrap_or(if self.filter.source.is_some() {
+                    "Removed source"
+                } else {
+                    "All sources"
+                });
+            egui::ComboBox::from_id_salt("source_filter")
+                .selected_text(selected_source)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.source, None, "All sources");
+                    for (source, _) in sources {
+                        ui.selectable_value(
+                            &mut self.filter.source,
+                            Some(source.id()),
+                            format!("{} {}", source.icon(), source.name()),
+                        );
+                    }
+                });
+
+            // Keep the selected project, even if it has no tasks at the moment
+            let projects: BTreeSet<String> = all_tasks
+                .iter()
+                .map(|t| t.project.clone())
+                .chain(self.filter.project.clone())
+                .collect();
+            egui::ComboBox::from_id_salt("project_filter")
+                .selected_text(
+                    self.filter
+                        .project
+                        .as_deref()
+                        .unwrap_or("All projects")
+                        .truncate_ellipse(30),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.project, None, "All projects");
+                    for project in projects {
+                        let caption = project.as_str().truncate_ellipse(40).to_string();
+                        ui.selectable_value(&mut self.filter.project, Some(project), caption);
+                    }
+                });
+
+            for due in DueFilter::ALL {
+                if due != DueFilter::Any
+                    && ui
+                        .selectable_label(self.filter.due == due, due.name())
+                        .clicked()
+                {
+                    self.filter.due = if self.filter.due == due {
+                        DueFilter::Any
+                    } else {
+                        due
+                    };
+                }
+            }
+
+            if self.filter.is_active() {
+                ui.separator();
+                ui.label(format!("{shown} of {} tasks", all_tasks.len()));
+                if ui.button("Clear").clicked() {
+                    self.filter = TaskFilter::default();
+                }
+            }
+        });
+    }
+
+    /// Choose a preset or combine several sort keys.
+    fn edit_sorting(ui: &mut Ui, sorting: &mut Sorting) {
+        let caption = PRESETS
+            .iter()
+            .find(|(_, keys)| *keys == sorting.keys.as_slice())
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| sorting.keys.iter().map(|k| k.name()).join(", "));
+        ui.menu_button(
+            format!("{} Sort: {caption}", egui_phosphor::regular::SORT_ASCENDING),
+            |ui| {
+                for (name, keys) in PRESETS {
+                    if ui.selectable_label(sorting.keys == keys, name).clicked() {
+                        sorting.keys = keys.to_vec();
+                    }
+                }
+                ui.separator();
+                ui.label("Sort by, in this order:");
+                for key in SortKey::ALL {
+                    let position = sorting.keys.iter().position(|k| *k == key);
+                    let caption = match position {
+                        Some(p) => format!("{}. {}", p + 1, key.name()),
+                        None => key.name().to_string(),
+                    };
+                    if ui.selectable_label(position.is_some(), caption).clicked() {
+                        match position {
+                            Some(p) => {
+                                sorting.keys.remove(p);
+                            }
+                            None => sorting.keys.push(key),
+                        }
+                    }
+                }
+                if sorting.keys.contains(&SortKey::Score) {
+                    ui.separator();
+                    ui.label("Score weights");
+                    let weights = &mut sorting.weights;
+                    ui.add(Slider::new(&mut weights.due, 0.0..=5.0).text("Due date"));
+                    ui.add(Slider::new(&mut weights.priority, 0.0..=5.0).text("Priority"));
+                    ui.add(Slider::new(&mut weights.age, 0.0..=5.0).text("Age"));
+                }
+            },
+        );
+    }
+
+    /// Select, save and delete named filters.
+    fn edit_saved_views(&mut self, ui: &mut Ui) {
+        let current_view = self
+            .views
+            .iter()
+            .find(|v| v.filter == self.filter)
+            .map(|v| v.name.as_str())
+            .unwrap_or("Views");
+        ui.menu_button(
+            format!("{} {}", egui_phosphor::regular::FUNNEL, current_view),
+            |ui| {
+                let mut remove_view = None;
+                for (i, view) in self.views.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .selectable_label(view.filter == self.filter, &view.name)
+                            .clicked()
+                        {
+                            self.filter = view.filter.clone();
+                            ui.close_menu();
+                        }
+                        if ui
+                            .small_button(egui_phosphor::regular::TRASH)
+                            .on_hover_text("Delete view")
+                            .clicked()
+                        {
+                            remove_view = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove_view {
+                    self.views.remove(i);
+                }
+                if !self.views.is_empty() {
+                    ui.separator();
+                }
+                ui.horizontal(|ui| {
+                    ui.add(
+                        TextEdit::singleline(&mut self.new_view_name)
+                            .hint_text("Name")
+                            .desired_width(100.0),
+                    );
+                    let name = self.new_view_name.trim();
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("Save filter"))
+                        .clicked()
+                    {
+                        let view = SavedView {
+                            name: name.to_string(),
+                            filter: self.filter.clone(),
+                        };
+                        // Saving under an existing name replaces the view
+                        if let Some(existing) = self.views.iter_mut().find(|v| v.name == view.name)
+                        {
+                            *existing = view;
+                        } else {
+                            self.views.push(view);
+                        }
+                        self.new_view_name.clear();
+                        ui.close_menu();
+                    }
+                });
+            },
+        );
+    }
+
     fn trigger_refresh(&mut self, manually_triggered: bool, ctx: Context) {
         self.last_refreshed = Instant::now();
         self.connection_error_for_source.clear();
@@ -368,6 +1710,26 @@ impl TaskPickerApp {
                     Slider::new(&mut self.settings.refresh_rate_seconds, 5..=120)
                         .text("Refresh Rate (seconds)"),
                 );
+                ui.separator();
+
+                let secrets_button = if self.task_manager.secrets().is_locked() {
+                    format!("{} Unlock secrets", egui_phosphor::regular::LOCK)
+                } else {
+                    format!("{} Secrets", egui_phosphor::regular::KEY)
+                };
+                if ui.button(secrets_button).clicked() {
+                    self.secret_settings = Some(SecretSettings {
+                        command: self.task_manager.secrets().command.clone(),
+                        passphrase: String::default(),
+                    });
+                }
+                ui.separator();
+                self.edit_saved_views(ui);
+                ui.separator();
+                // The layout is right to left, so add the views in reverse
+                for view in TaskView::ALL.into_iter().rev() {
+                    ui.selectable_value(&mut self.settings.view, view, view.caption());
+                }
             });
         });
 
@@ -382,6 +1744,7 @@ impl TaskPickerApp {
 
                 for i in 0..self.task_manager.sources().len() {
                     let (s, enabled) = &mut self.task_manager.source_ref_mut(i);
+                    let source_id = s.id();
                     let source_name = s.name();
                     let source_icon = s.icon();
 
@@ -405,7 +1768,7 @@ impl TaskPickerApp {
                                 ui.close_menu();
                             }
                         });
-                        if self.connection_error_for_source.contains(source_name) {
+                        if self.connection_error_for_source.contains(&source_id) {
                             ui.label("📵").on_hover_ui(|ui| {
                                 ui.label("Not Connected");
                             });
@@ -413,12 +1776,25 @@ impl TaskPickerApp {
                     });
                 }
                 if let Some(i) = remove_source {
-                    self.task_manager.remove_source(i);
+                    // The secret is deleted together with the source, so
+                    // remember it in case the removal is undone
+                    let secret = self
+                        .task_manager
+                        .secrets()
+                        .get(&self.task_manager.sources()[i].0);
+                    let (source, enabled) = self.task_manager.remove_source(i);
+                    self.removed_sources.push(RemovedSource {
+                        source,
+                        enabled,
+                        secret,
+                        removed_at: Instant::now(),
+                    });
+                    ctx.request_repaint_after(UNDO_REMOVE_DURATION);
                 } else if let Some(i) = edit_source {
                     let source = self.task_manager.sources()[i].0.clone();
-                    self.currently_edited_secret = source.secret().unwrap_or_default();
+                    self.currently_edited_secret =
+                        self.task_manager.secrets().get(&source).unwrap_or_default();
                     self.edit_source = Some(source);
-                    self.existing_edit_source = true;
                 }
 
                 if refresh {
@@ -433,21 +1809,18 @@ impl TaskPickerApp {
                         .button(egui::RichText::new(format!("{} CalDAV", CALDAV_ICON)))
                         .clicked()
                     {
-                        self.existing_edit_source = false;
                         self.edit_source = Some(TaskSource::CalDav(CalDavSource::default()));
                     }
                     if ui
                         .button(egui::RichText::new(format!("{} GitHub", GITHUB_ICON)))
                         .clicked()
                     {
-                        self.existing_edit_source = false;
                         self.edit_source = Some(TaskSource::GitHub(GitHubSource::default()));
                     }
                     if ui
                         .button(egui::RichText::new(format!("{} GitLab", GITLAB_ICON)))
                         .clicked()
                     {
-                        self.existing_edit_source = false;
                         self.edit_source = Some(TaskSource::GitLab(GitLabSource::default()));
                     }
                     if ui
@@ -457,13 +1830,17 @@ impl TaskPickerApp {
                         )))
                         .clicked()
                     {
-                        self.existing_edit_source = false;
                         self.edit_source =
                             Some(TaskSource::OpenProject(OpenProjectSource::default()));
                     }
                 });
             });
 
+        self.show_undo_remove(ctx);
+        if self.secret_settings.is_some() {
+            self.edit_secret_settings(ctx);
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             self.messages.show(ctx);
 
@@ -479,10 +1856,53 @@ impl TaskPickerApp {
                 {
                     self.trigger_refresh(true, ctx.clone());
                 }
+                ui.separator();
+                ui.checkbox(&mut self.settings.hide_subtasks, "Hide subtasks");
+                ui.separator();
+                Self::edit_sorting(ui, &mut self.settings.sorting);
+                ui.separator();
+                ui.add_enabled_ui(self.settings.view != TaskView::Calendar, |ui| {
+                    egui::ComboBox::from_id_salt("grouping")
+                        .selected_text(format!(
+                            "{} {}",
+                            egui_phosphor::regular::ROWS,
+                            self.settings.grouping.name()
+                        ))
+                        .show_ui(ui, |ui| {
+                            for grouping in Grouping::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.grouping,
+                                    grouping,
+                                    grouping.name(),
+                                );
+                            }
+                        });
+                });
             });
-            ScrollArea::vertical().show(ui, |ui| {
-                self.render_all_tasks(self.task_manager.tasks(), ui)
-            });
+            let all_tasks = self.task_manager.tasks();
+            let now = self.overwrite_current_time.unwrap_or_else(Utc::now);
+            // An invalid expression is shown as error and does not hide tasks
+            let (expression, filter_error) = match self.filter.expression() {
+                Ok(expression) => (expression, None),
+                Err(e) => (Expression::default(), Some(e.to_string())),
+            };
+            let mut filtered_tasks: Vec<Task> = all_tasks
+                .iter()
+                .filter(|t| self.filter.matches(&expression, t, now))
+                .cloned()
+                .collect();
+            let source_order: Vec<Uuid> = self
+                .task_manager
+                .sources()
+                .iter()
+                .map(|(s, _)| s.id())
+                .collect();
+            self.settings
+                .sorting
+                .sort(&mut filtered_tasks, &source_order, now);
+            self.render_filter_bar(ui, &all_tasks, filtered_tasks.len(), filter_error);
+            ui.separator();
+            ScrollArea::vertical().show(ui, |ui| self.render_all_tasks(filtered_tasks, ui));
         });
 
         if self.edit_source.is_some() {
@@ -499,11 +1919,10 @@ impl TaskPickerApp {
         for (source, active) in self.task_manager.sources() {
             if *active {
                 let source_name = source.name();
-                if let Some(err) = self.task_manager.get_and_clear_last_err(source.name()) {
+                if let Some(err) = self.task_manager.get_and_clear_last_err(source.id()) {
                     if is_dns_error(&err) {
                         // DNS errors indicate a connection problem on our side
-                        self.connection_error_for_source
-                            .insert(source_name.to_string());
+                        self.connection_error_for_source.insert(source.id());
                     } else {
                         error!("Error querying source \"{source_name}\". {}", &err);
                         let shortened_message = err
@@ -538,6 +1957,214 @@ impl eframe::App for TaskPickerApp {
     }
 }
 
+/// Show the priority, status and labels of a task as small badges.
+fn render_badges(ui: &mut Ui, task: &Task) {
+    let show_kind = task.kind != TaskKind::Todo;
+    if !show_kind && task.priority.is_none() && task.status.is_none() && task.labels.is_empty() {
+        return;
+    }
+    // Use the overwritten text color, e.g. for overdue tasks, so the
+    // badges are still readable.
+    let visuals = ui.visuals();
+    let color = |c: Color32| visuals.override_text_color.unwrap_or(c);
+    let priority = task.priority.map(|p| match p {
+        1..=4 => ("High", color(visuals.error_fg_color)),
+        5 => ("Medium", color(visuals.warn_fg_color)),
+        _ => ("Low", color(visuals.weak_text_color())),
+    });
+    let status_color = color(visuals.hyperlink_color);
+    let label_color = color(visuals.text_color());
+
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 3.0;
+        if show_kind {
+            badge(ui, task.kind.name().to_string(), label_color);
+        }
+        if let Some((caption, color)) = priority {
+            badge(
+                ui,
+                format!("{} {caption}", egui_phosphor::regular::FLAG),
+                color,
+            );
+        }
+        if let Some(status) = &task.status {
+            badge(ui, status.clone(), status_color);
+        }
+        for label in &task.labels {
+            badge(
+                ui,
+                format!(
+                    "{} {}",
+                    egui_phosphor::regular::TAG,
+                    label.as_str().truncate_ellipse(20)
+                ),
+                label_color,
+            );
+        }
+    });
+}
+
+/// Render the beginning of the description of a task, with formatting if it
+/// is Markdown.
+fn description_layout(ui: &Ui, task: &Task) -> LayoutJob {
+    let spans = if task.markdown {
+        markdown::parse(&task.description)
+    } else {
+        vec![markdown::Span {
+            text: task.description.clone(),
+            style: markdown::Style::default(),
+        }]
+    };
+    let mut job = LayoutJob::default();
+    for span in markdown::truncate(spans, 100) {
+        let mut text = RichText::new(span.text);
+        if span.style.strong {
+            text = text.strong();
+        }
+        if span.style.emphasis {
+            text = text.italics();
+        }
+        if span.style.code {
+            text = text.code();
+        }
+        text.append_to(&mut job, ui.style(), FontSelection::Default, Align::Center);
+    }
+    job
+}
+
+/// True if the task is due now or was due in the past.
+fn is_overdue(task: &Task, now: DateTime<Utc>) -> bool {
+    task.due.is_some_and(|due| due <= now)
+}
+
+/// Color to mark a due date within the next two days. Overdue tasks are
+/// already highlighted with a red background and get no further highlight.
+fn due_soon_color(ui: &Ui, due: DateTime<Utc>, now: DateTime<Utc>) -> Option<Color32> {
+    let hours_to_finish = due.signed_duration_since(now).num_hours();
+    if due <= now {
+        None
+    } else if hours_to_finish < 24 {
+        Some(ui.visuals().error_fg_color)
+    } else if hours_to_finish < 48 {
+        Some(ui.visuals().warn_fg_color)
+    } else {
+        None
+    }
+}
+
+/// Describe why a task is on the list, e.g. "Review requested by Alice on MR
+/// !12".
+fn reason_text(reason: &TaskReason) -> String {
+    let action = match reason.action.as_str() {
+        "assigned" => "Assigned".to_string(),
+        "mentioned" => "Mentioned".to_string(),
+        "directly_addressed" => "Addressed".to_string(),
+        "marked" => "Added".to_string(),
+        "review_requested" => "Review requested".to_string(),
+        "review_submitted" => "Reviewed".to_string(),
+        "approval_required" => "Approval required".to_string(),
+        "member_access_requested" => "Access requested".to_string(),
+        "build_failed" => "Pipeline failed".to_string(),
+        "unmergeable" => "Cannot be merged".to_string(),
+        "merge_train_removed" => "Removed from merge train".to_string(),
+        "" => "Todo".to_string(),
+        // Show unknown actions as they are, e.g. "Okr checkin requested"
+        other => {
+            let mut chars = other.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+                .replace('_', " ")
+        }
+    };
+    let mut text = action;
+    if let Some(actor) = &reason.actor {
+        text.push_str(" by ");
+        text.push_str(actor);
+    }
+    if let Some(reference) = &reason.target_reference {
+        let target = match reason.target_type.as_deref() {
+            Some("MergeRequest") => "MR",
+            Some("PullRequest") => "PR",
+            Some("Issue") => "issue",
+            Some("WorkItem") => "work item",
+            Some("Epic") => "epic",
+            _ => "",
+        };
+        if !target.is_empty() {
+            text.push_str(&format!(" on {target} {reference}"));
+        }
+    }
+    text
+}
+
+/// Short representation of the time since the task has been created, e.g.
+/// "5h" or "3w".
+fn format_age(created: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let age = now.signed_duration_since(created);
+    if age.num_hours() < 1 {
+        format!("{}m", age.num_minutes().max(0))
+    } else if age.num_days() < 1 {
+        format!("{}h", age.num_hours())
+    } else if age.num_weeks() < 2 {
+        format!("{}d", age.num_days())
+    } else if age.num_days() < 365 {
+        format!("{}w", age.num_weeks())
+    } else {
+        format!("{}y", age.num_days() / 365)
+    }
+}
+
+/// Context menu to open the web page of a task.
+fn open_link_menu(response: &egui::Response, task: &Task) {
+    if let Some(url) = &task.url {
+        response.context_menu(|ui| {
+            if ui
+                .button(format!("{} Open link", egui_phosphor::regular::LINK))
+                .clicked()
+            {
+                ui.ctx().open_url(egui::OpenUrl::same_tab(url));
+                ui.close_menu();
+            }
+        });
+    }
+}
+
+/// Width of the title column of the list view, which fills the space that is
+/// not used by the other columns.
+fn list_title_width(ui: &Ui) -> f32 {
+    let fixed = 2.0 * ICON_COLUMN_WIDTH
+        + PROJECT_COLUMN_WIDTH
+        + DUE_COLUMN_WIDTH
+        + AGE_COLUMN_WIDTH
+        + 6.0 * ui.spacing().item_spacing.x
+        + 8.0;
+    (ui.available_width() - fixed).max(100.0)
+}
+
+/// A cell of the list view with a fixed width.
+fn list_cell(ui: &mut Ui, width: f32, add_contents: impl FnOnce(&mut Ui)) {
+    ui.allocate_ui_with_layout(
+        Vec2::new(width, ui.spacing().interact_size.y),
+        Layout::left_to_right(Align::Center),
+        |ui| {
+            ui.set_width(width);
+            add_contents(ui);
+        },
+    );
+}
+
+fn badge(ui: &mut Ui, text: String, color: Color32) {
+    egui::Frame::new()
+        .stroke(egui::Stroke::new(1.0, color))
+        .corner_radius(4.0)
+        .inner_margin(egui::Margin::symmetric(3, 0))
+        .show(ui, |ui| {
+            ui.label(RichText::new(text).small().color(color));
+        });
+}
+
 fn is_dns_error(err: &anyhow::Error) -> bool {
     if let Some(Error::Ical(caldav_err)) = err.downcast_ref::<minicaldav::Error>() {
         // The errors only transport the string, so we have to search the error
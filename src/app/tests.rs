@@ -1,13 +1,15 @@
 use std::{sync::Once, vec};
 
-use chrono::Days;
-use egui_kittest::Harness;
+use chrono::{Days, TimeDelta};
+use egui_kittest::{kittest::Queryable, Harness};
 
 use super::*;
+use crate::{secrets::SecretStore, sources::SourceKind};
 
 static INIT: Once = Once::new();
 
 #[test]
+#[allow(clippy::zero_prefixed_literal, clippy::field_reassign_with_default)]
 fn test_render_single_task_with_description() {
     INIT.call_once(|| std::env::set_var("TZ", "CET"));
     let now = Utc.with_ymd_and_hms(2022, 03, 19, 17, 42, 00).unwrap();
@@ -17,15 +19,20 @@ fn test_render_single_task_with_description() {
     app.app_version = "0.0.0".to_string();
 
     let task = Task {
-        project: format!("{} family", CALDAV_ICON),
+        project: "family".to_string(),
+        source_kind: Some(SourceKind::CalDav),
         title: "Buy presents".to_string(),
         description: "They should be surprising.\n\nBut not that surprising!".to_string(),
         due: Some(Utc.with_ymd_and_hms(2022, 12, 24, 20, 0, 0).unwrap()),
         created: Some(Utc.with_ymd_and_hms(2022, 09, 1, 12, 24, 30).unwrap()),
         id: None,
+        ..Default::default()
     };
     app.task_manager.expect_tasks().return_const(vec![task]);
     app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
     app.task_manager.expect_refresh().return_const(());
 
     let mut harness = Harness::new(|ctx| {
@@ -39,6 +46,7 @@ fn test_render_single_task_with_description() {
 }
 
 #[test]
+#[allow(clippy::zero_prefixed_literal, clippy::field_reassign_with_default)]
 fn test_render_task_grid() {
     INIT.call_once(|| std::env::set_var("TZ", "CET"));
     let now = Utc.with_ymd_and_hms(2023, 03, 19, 17, 42, 00).unwrap();
@@ -54,6 +62,7 @@ fn test_render_task_grid() {
         due: now.checked_add_days(Days::new(20)),
         created: now.checked_sub_days(Days::new(10)),
         id: Some("task_relaxed".to_string()),
+        ..Default::default()
     };
     let task_due_tomorrow = Task {
         project: "project".to_string(),
@@ -62,6 +71,7 @@ fn test_render_task_grid() {
         due: Some(Utc.with_ymd_and_hms(2023, 03, 20, 20, 42, 00).unwrap()),
         created: now.checked_sub_days(Days::new(10)),
         id: Some("task_due_tomorrow".to_string()),
+        ..Default::default()
     };
 
     let task_due_today = Task {
@@ -71,12 +81,16 @@ fn test_render_task_grid() {
         due: Some(Utc.with_ymd_and_hms(2023, 03, 19, 19, 42, 00).unwrap()),
         created: now.checked_sub_days(Days::new(10)),
         id: Some("task_due_today".to_string()),
+        ..Default::default()
     };
 
     let tasks = vec![task_due_today, task_due_tomorrow, task_relaxed];
 
     app.task_manager.expect_tasks().return_const(tasks);
     app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
     app.task_manager.expect_refresh().return_const(());
 
     let mut harness = Harness::new(|ctx| {
@@ -88,3 +102,483 @@ fn test_render_task_grid() {
     harness.run_steps(5);
     harness.snapshot("task_grid");
 }
+
+#[test]
+fn test_render_single_task_with_details() {
+    INIT.call_once(|| std::env::set_var("TZ", "CET"));
+    let now = Utc.with_ymd_and_hms(2024, 5, 2, 9, 0, 0).unwrap();
+    let mut app = TaskPickerApp {
+        overwrite_current_time: Some(now),
+        app_version: "0.0.0".to_string(),
+        ..Default::default()
+    };
+
+    let task = Task {
+        project: "work".to_string(),
+        source_kind: Some(SourceKind::CalDav),
+        title: "Prepare workshop".to_string(),
+        description: "Slides and exercises".to_string(),
+        due: Some(Utc.with_ymd_and_hms(2024, 5, 10, 12, 0, 0).unwrap()),
+        id: Some("workshop".to_string()),
+        priority: Some(2),
+        percent_complete: Some(40),
+        labels: vec!["teaching".to_string(), "travel".to_string()],
+        location: Some("Lecture hall".to_string()),
+        url: Some("https://example.com/workshop".to_string()),
+        status: Some("In progress".to_string()),
+        ..Default::default()
+    };
+    app.task_manager.expect_tasks().return_const(vec![task]);
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        ctx.set_theme(egui::Theme::Light);
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(5);
+    harness.snapshot("single_task_with_details");
+}
+
+#[test]
+fn test_render_hidden_subtasks() {
+    INIT.call_once(|| std::env::set_var("TZ", "CET"));
+    let now = Utc.with_ymd_and_hms(2024, 5, 2, 9, 0, 0).unwrap();
+    let mut app = TaskPickerApp {
+        overwrite_current_time: Some(now),
+        app_version: "0.0.0".to_string(),
+        ..Default::default()
+    };
+    app.settings.hide_subtasks = true;
+
+    let parent = Task {
+        project: "project".to_string(),
+        title: "Release".to_string(),
+        id: Some("release".to_string()),
+        ..Default::default()
+    };
+    let subtasks = ["Write changelog", "Tag version"].map(|title| Task {
+        project: "project".to_string(),
+        title: title.to_string(),
+        id: Some(title.to_string()),
+        parent: Some("release".to_string()),
+        ..Default::default()
+    });
+    let mut tasks = vec![parent];
+    tasks.extend(subtasks);
+    app.task_manager.expect_tasks().return_const(tasks);
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        ctx.set_theme(egui::Theme::Light);
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(5);
+    harness.get_by_label("Subtasks (2)").click();
+    harness.run_steps(5);
+    harness.snapshot("hidden_subtasks");
+}
+
+#[test]
+fn test_undo_remove_source() {
+    let mut app = TaskPickerApp {
+        app_version: "0.0.0".to_string(),
+        removed_sources: vec![
+            RemovedSource {
+                source: TaskSource::GitHub(GitHubSource::default()),
+                enabled: false,
+                secret: Some("token".to_string()),
+                removed_at: Instant::now(),
+            },
+            RemovedSource {
+                source: TaskSource::GitLab(GitLabSource::default()),
+                enabled: true,
+                secret: Some("other token".to_string()),
+                removed_at: Instant::now(),
+            },
+        ],
+        ..Default::default()
+    };
+    app.task_manager.expect_tasks().return_const(vec![]);
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+    app.task_manager
+        .expect_restore_source()
+        .withf(|source, enabled, secret| {
+            source.name() == "GitHub" && !*enabled && secret == "token"
+        })
+        .times(1)
+        .return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.run_steps(2);
+    // Each removed source can be restored on its own
+    assert_eq!(2, harness.query_all_by_label("Undo").count());
+    harness.query_all_by_label("Undo").next().unwrap().click();
+    harness.run_steps(2);
+    assert_eq!(1, harness.query_all_by_label("Undo").count());
+    assert!(harness
+        .query_by_label("Removed source \"GitLab\"")
+        .is_some());
+}
+
+#[test]
+fn test_reset_filter_of_removed_source() {
+    let source = TaskSource::GitHub(GitHubSource::default());
+    let source_id = source.id();
+    let filter = TaskFilter {
+        source: Some(source_id),
+        ..Default::default()
+    };
+    let mut app = TaskPickerApp {
+        app_version: "0.0.0".to_string(),
+        filter: filter.clone(),
+        views: vec![SavedView {
+            name: "GitHub".to_string(),
+            filter,
+        }],
+        removed_sources: vec![RemovedSource {
+            source,
+            enabled: true,
+            secret: None,
+            removed_at: Instant::now(),
+        }],
+        ..Default::default()
+    };
+    app.task_manager.expect_tasks().return_const(vec![]);
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.run_steps(2);
+    drop(harness);
+    // The filter is kept while the removal can be undone
+    assert_eq!(Some(source_id), app.filter.source);
+
+    app.removed_sources[0].removed_at = Instant::now() - UNDO_REMOVE_DURATION * 2;
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.run_steps(2);
+    drop(harness);
+    assert_eq!(None, app.filter.source);
+    assert_eq!(None, app.views[0].filter.source);
+}
+
+#[test]
+fn test_filter_tasks() {
+    let mut app = TaskPickerApp {
+        app_version: "0.0.0".to_string(),
+        filter: TaskFilter {
+            search: "present".to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let tasks = ["Buy presents", "Clean up"].map(|title| Task {
+        project: "family".to_string(),
+        title: title.to_string(),
+        ..Default::default()
+    });
+    app.task_manager.expect_tasks().return_const(tasks.to_vec());
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(2);
+    assert!(harness.query_by_label("Buy presents").is_some());
+    assert!(harness.query_by_label("Clean up").is_none());
+    assert!(harness.query_by_label("1 of 2 tasks").is_some());
+
+    harness.get_by_label("Clear").click();
+    harness.run_steps(2);
+    assert!(harness.query_by_label("Clean up").is_some());
+}
+
+#[test]
+fn test_invalid_filter_expression() {
+    let mut app = TaskPickerApp {
+        app_version: "0.0.0".to_string(),
+        filter: TaskFilter {
+            search: "label<3".to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let task = Task {
+        title: "Buy presents".to_string(),
+        ..Default::default()
+    };
+    app.task_manager.expect_tasks().return_const(vec![task]);
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(2);
+    // The error is shown and the expression does not hide any task
+    assert!(harness
+        .query_by_label_contains("'label' can't be compared")
+        .is_some());
+    assert!(harness.query_by_label("Buy presents").is_some());
+}
+
+#[test]
+fn test_group_tasks_by_project() {
+    let mut app = TaskPickerApp {
+        app_version: "0.0.0".to_string(),
+        ..Default::default()
+    };
+    app.settings.grouping = Grouping::Project;
+    let tasks =
+        [("work", "Prepare workshop"), ("family", "Buy presents")].map(|(project, title)| Task {
+            project: project.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        });
+    app.task_manager.expect_tasks().return_const(tasks.to_vec());
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(2);
+    assert!(harness.query_by_label("Buy presents").is_some());
+
+    // Collapsing a group hides its tasks
+    harness.get_by_label("family (1)").click();
+    harness.run_steps(5);
+    assert!(harness.query_by_label("Buy presents").is_none());
+    assert!(harness.query_by_label("Prepare workshop").is_some());
+}
+
+#[test]
+fn test_render_task_list() {
+    INIT.call_once(|| std::env::set_var("TZ", "CET"));
+    let now = Utc.with_ymd_and_hms(2023, 3, 19, 17, 42, 0).unwrap();
+    let mut app = TaskPickerApp {
+        overwrite_current_time: Some(now),
+        app_version: "0.0.0".to_string(),
+        ..Default::default()
+    };
+    app.settings.view = TaskView::List;
+
+    let tasks = [
+        ("Overdue", "infra", now.checked_sub_days(Days::new(1))),
+        (
+            "Due Today",
+            "project",
+            now.checked_add_signed(TimeDelta::hours(2)),
+        ),
+        ("Far away", "docs", now.checked_add_days(Days::new(20))),
+    ]
+    .map(|(title, project, due)| Task {
+        id: Some(title.to_string()),
+        title: title.to_string(),
+        project: project.to_string(),
+        source_kind: Some(SourceKind::GitLab),
+        due,
+        created: now.checked_sub_days(Days::new(10)),
+        url: Some("https://example.com".to_string()),
+        ..Default::default()
+    });
+    app.task_manager.expect_tasks().return_const(tasks.to_vec());
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        ctx.set_theme(egui::Theme::Light);
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(5);
+    harness.snapshot("task_list");
+
+    // Clicking on the title selects the task
+    harness.get_by_label("Far away").click();
+    harness.run_steps(2);
+    drop(harness);
+    assert_eq!(Some(tasks[2].get_id()), app.selected_task);
+}
+
+#[test]
+fn test_sort_list_by_column() {
+    let mut app = TaskPickerApp {
+        app_version: "0.0.0".to_string(),
+        ..Default::default()
+    };
+    app.settings.view = TaskView::List;
+    app.task_manager.expect_tasks().return_const(vec![]);
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(800.0, 600.0));
+    harness.run_steps(2);
+    harness.get_by_label("Project").click();
+    harness.run_steps(2);
+    drop(harness);
+    assert_eq!(Some(&SortKey::Project), app.settings.sorting.keys.first());
+}
+
+#[test]
+fn test_format_age() {
+    let now = Utc.with_ymd_and_hms(2024, 5, 2, 9, 0, 0).unwrap();
+    assert_eq!("5m", format_age(now - TimeDelta::minutes(5), now));
+    assert_eq!("3h", format_age(now - TimeDelta::hours(3), now));
+    assert_eq!("10d", format_age(now - TimeDelta::days(10), now));
+    assert_eq!("3w", format_age(now - TimeDelta::days(22), now));
+    assert_eq!("2y", format_age(now - TimeDelta::days(800), now));
+}
+
+#[test]
+fn test_reason_text() {
+    let reason =
+        |action: &str, actor: Option<&str>, target_type: &str, reference: &str| TaskReason {
+            action: action.to_string(),
+            actor: actor.map(|a| a.to_string()),
+            target_type: Some(target_type.to_string()),
+            target_reference: Some(reference.to_string()),
+        };
+    assert_eq!(
+        "Review requested by Alice on MR !123",
+        reason_text(&reason(
+            "review_requested",
+            Some("Alice"),
+            "MergeRequest",
+            "!123"
+        ))
+    );
+    assert_eq!(
+        "Pipeline failed on MR !123",
+        reason_text(&reason("build_failed", None, "MergeRequest", "!123"))
+    );
+    assert_eq!(
+        "Review requested on PR #1348",
+        reason_text(&reason("review_requested", None, "PullRequest", "#1348"))
+    );
+    assert_eq!(
+        "Okr checkin requested by bob on issue #5",
+        reason_text(&reason("okr_checkin_requested", Some("bob"), "Issue", "#5"))
+    );
+}
+
+#[test]
+fn test_render_calendar() {
+    INIT.call_once(|| std::env::set_var("TZ", "CET"));
+    // A Thursday
+    let now = Utc.with_ymd_and_hms(2023, 3, 23, 9, 0, 0).unwrap();
+    let mut app = TaskPickerApp {
+        overwrite_current_time: Some(now),
+        app_version: "0.0.0".to_string(),
+        ..Default::default()
+    };
+    app.settings.view = TaskView::Calendar;
+
+    let tasks = [
+        ("Missed deadline", None, now.checked_sub_days(Days::new(2))),
+        (
+            "Due soon",
+            None,
+            now.checked_add_signed(TimeDelta::hours(5)),
+        ),
+        (
+            "Write report",
+            now.checked_sub_days(Days::new(1)),
+            now.checked_add_days(Days::new(2)),
+        ),
+        ("Some day", None, None),
+    ]
+    .map(|(title, start, due)| Task {
+        id: Some(title.to_string()),
+        title: title.to_string(),
+        source_kind: Some(SourceKind::CalDav),
+        start,
+        due,
+        ..Default::default()
+    });
+    app.task_manager.expect_tasks().return_const(tasks.to_vec());
+    app.task_manager.expect_sources().return_const(vec![]);
+    app.task_manager
+        .expect_secrets()
+        .return_const(SecretStore::default());
+    app.task_manager.expect_refresh().return_const(());
+
+    let mut harness = Harness::new(|ctx| {
+        ctx.set_theme(egui::Theme::Light);
+        app.init_with_egui_context(ctx);
+        app.render(ctx);
+    });
+    harness.set_size(Vec2::new(1000.0, 600.0));
+    harness.run_steps(5);
+    harness.snapshot("calendar");
+
+    // Bars can be selected like cards
+    harness.get_by_label("Write report").click();
+    harness.run_steps(2);
+    // Moving to the next week hides the tasks of this week
+    harness
+        .get_by_label(egui_phosphor::regular::CARET_RIGHT)
+        .click();
+    harness.run_steps(2);
+    assert!(harness.query_by_label("Missed deadline").is_none());
+    assert!(harness
+        .query_by_label(&format!("{} Some day", CALDAV_ICON))
+        .is_some());
+    drop(harness);
+    assert_eq!(Some(tasks[2].get_id()), app.selected_task);
+    assert_eq!(1, app.calendar_offset);
+}
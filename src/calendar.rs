@@ -0,0 +1,168 @@
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::tasks::Task;
+
+/// Number of days shown on one page of the calendar.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CalendarRange {
+    #[default]
+    Week,
+    Month,
+}
+
+impl CalendarRange {
+    pub const ALL: [CalendarRange; 2] = [CalendarRange::Week, CalendarRange::Month];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CalendarRange::Week => "Week",
+            CalendarRange::Month => "Month",
+        }
+    }
+}
+
+/// The weeks shown on one page of the calendar. Weeks start on Monday.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarPage {
+    pub title: String,
+    pub weeks: Vec<[NaiveDate; 7]>,
+    /// For a month page, the first day of the month. Days of the previous and
+    /// next month fill the first and last week.
+    pub month: Option<NaiveDate>,
+}
+
+fn start_of_week(day: NaiveDate) -> NaiveDate {
+    day - Days::new(day.weekday().num_days_from_monday().into())
+}
+
+fn week_of(monday: NaiveDate) -> [NaiveDate; 7] {
+    std::array::from_fn(|i| monday + Days::new(i as u64))
+}
+
+fn shift_months(day: NaiveDate, offset: i32) -> NaiveDate {
+    let months = Months::new(offset.unsigned_abs());
+    if offset < 0 {
+        day - months
+    } else {
+        day + months
+    }
+}
+
+impl CalendarPage {
+    /// The page that is `offset` weeks or months away from the one containing
+    /// `today`.
+    pub fn new(range: CalendarRange, today: NaiveDate, offset: i32) -> Self {
+        match range {
+            CalendarRange::Week => {
+                let monday = start_of_week(today) + chrono::TimeDelta::weeks(offset.into());
+                let week = week_of(monday);
+                let title = if week[0].month() == week[6].month() {
+                    format!("{} – {}", week[0].format("%d"), week[6].format("%d %b %Y"))
+                } else if week[0].year() == week[6].year() {
+                    format!(
+                        "{} – {}",
+                        week[0].format("%d %b"),
+                        week[6].format("%d %b %Y")
+                    )
+                } else {
+                    format!(
+                        "{} – {}",
+                        week[0].format("%d %b %Y"),
+                        week[6].format("%d %b %Y")
+                    )
+                };
+                CalendarPage {
+                    title,
+                    weeks: vec![week],
+                    month: None,
+                }
+            }
+            CalendarRange::Month => {
+                let first = shift_months(today.with_day(1).unwrap_or(today), offset);
+                let last = first + Months::new(1) - Days::new(1);
+                let mut weeks = Vec::new();
+                let mut monday = start_of_week(first);
+                while monday <= last {
+                    weeks.push(week_of(monday));
+                    monday = monday + Days::new(7);
+                }
+                CalendarPage {
+                    title: first.format("%B %Y").to_string(),
+                    weeks,
+                    month: Some(first),
+                }
+            }
+        }
+    }
+
+    /// True if the day belongs to the month of a month page.
+    pub fn is_in_month(&self, day: NaiveDate) -> bool {
+        self.month
+            .is_none_or(|month| month.year() == day.year() && month.month() == day.month())
+    }
+}
+
+/// The local days from the start to the due date of a task, or only the due
+/// date if the task has no start or starts after it is due. Tasks without due
+/// date have no span.
+pub fn task_span(task: &Task) -> Option<(NaiveDate, NaiveDate)> {
+    let local_day = |date: DateTime<Utc>| date.with_timezone(&Local).date_naive();
+    let due = local_day(task.due?);
+    let start = task.start.map(local_day).filter(|start| *start < due);
+    Some((start.unwrap_or(due), due))
+}
+
+/// A task shown as bar over one or several days of a week.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarBar {
+    /// Index of the task in the list of tasks the bars are created for.
+    pub task: usize,
+    /// First and last column (day of the week) covered by the bar.
+    pub first: usize,
+    pub last: usize,
+    /// The task started before this week.
+    pub continues_before: bool,
+    /// The task is due after this week.
+    pub continues_after: bool,
+}
+
+/// Arrange the bars of all tasks that are scheduled in this week in lanes, so
+/// that bars in the same lane do not overlap. Earlier and longer bars get the
+/// upper lanes, otherwise the order of the tasks is kept.
+pub fn week_lanes(tasks: &[Task], week: &[NaiveDate; 7]) -> Vec<Vec<CalendarBar>> {
+    let mut bars: Vec<CalendarBar> = tasks
+        .iter()
+        .enumerate()
+        .filter_map(|(i, task)| {
+            let (start, due) = task_span(task)?;
+            if due < week[0] || start > week[6] {
+                return None;
+            }
+            let column = |day: NaiveDate| week.iter().position(|d| *d == day);
+            Some(CalendarBar {
+                task: i,
+                first: column(start).unwrap_or(0),
+                last: column(due).unwrap_or(6),
+                continues_before: start < week[0],
+                continues_after: due > week[6],
+            })
+        })
+        .collect();
+    bars.sort_by_key(|bar| (bar.first, 6 - bar.last));
+
+    let mut lanes: Vec<Vec<CalendarBar>> = Vec::new();
+    for bar in bars {
+        let free_lane = lanes
+            .iter_mut()
+            .find(|lane| lane.last().is_some_and(|other| other.last < bar.first));
+        match free_lane {
+            Some(lane) => lane.push(bar),
+            None => lanes.push(vec![bar]),
+        }
+    }
+    lanes
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,128 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+
+use crate::tasks::Task;
+
+use super::{task_span, week_lanes, CalendarPage, CalendarRange};
+
+fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn local_time(year: i32, month: u32, day: u32) -> Option<DateTime<Utc>> {
+    Some(
+        Local
+            .with_ymd_and_hms(year, month, day, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc),
+    )
+}
+
+#[test]
+fn test_week_page() {
+    // A Thursday
+    let today = day(2024, 5, 2);
+    let page = CalendarPage::new(CalendarRange::Week, today, 0);
+    assert_eq!("29 Apr – 05 May 2024", page.title);
+    assert_eq!(1, page.weeks.len());
+    assert_eq!(day(2024, 4, 29), page.weeks[0][0]);
+    assert_eq!(day(2024, 5, 5), page.weeks[0][6]);
+    assert!(page.is_in_month(day(2024, 4, 29)));
+
+    let next = CalendarPage::new(CalendarRange::Week, today, 1);
+    assert_eq!("06 – 12 May 2024", next.title);
+    let previous = CalendarPage::new(CalendarRange::Week, today, -1);
+    assert_eq!(day(2024, 4, 22), previous.weeks[0][0]);
+}
+
+#[test]
+fn test_month_page() {
+    let today = day(2024, 5, 2);
+    let page = CalendarPage::new(CalendarRange::Month, today, 0);
+    assert_eq!("May 2024", page.title);
+    // From Monday, 29 April to Sunday, 2 June
+    assert_eq!(5, page.weeks.len());
+    assert_eq!(day(2024, 4, 29), page.weeks[0][0]);
+    assert_eq!(day(2024, 6, 2), page.weeks[4][6]);
+    assert!(!page.is_in_month(day(2024, 4, 30)));
+    assert!(page.is_in_month(day(2024, 5, 31)));
+
+    let previous = CalendarPage::new(CalendarRange::Month, today, -5);
+    assert_eq!("December 2023", previous.title);
+    let next = CalendarPage::new(CalendarRange::Month, day(2024, 1, 31), 1);
+    assert_eq!("February 2024", next.title);
+}
+
+#[test]
+fn test_task_span() {
+    let undated = Task::default();
+    assert_eq!(None, task_span(&undated));
+
+    let due_only = Task {
+        due: local_time(2024, 5, 3),
+        ..Default::default()
+    };
+    assert_eq!(
+        Some((day(2024, 5, 3), day(2024, 5, 3))),
+        task_span(&due_only)
+    );
+
+    let with_start = Task {
+        start: local_time(2024, 4, 25),
+        ..due_only.clone()
+    };
+    assert_eq!(
+        Some((day(2024, 4, 25), day(2024, 5, 3))),
+        task_span(&with_start)
+    );
+
+    // A start after the due date is ignored
+    let invalid_start = Task {
+        start: local_time(2024, 5, 10),
+        ..due_only
+    };
+    assert_eq!(
+        Some((day(2024, 5, 3), day(2024, 5, 3))),
+        task_span(&invalid_start)
+    );
+}
+
+#[test]
+fn test_week_lanes() {
+    let task = |start, due| Task {
+        start,
+        due,
+        ..Default::default()
+    };
+    let tasks = vec![
+        task(None, local_time(2024, 5, 3)),
+        task(local_time(2024, 4, 25), local_time(2024, 4, 30)),
+        task(None, local_time(2024, 5, 1)),
+        task(None, None),
+        task(local_time(2024, 5, 4), local_time(2024, 5, 8)),
+        task(None, local_time(2024, 5, 20)),
+    ];
+    let week = CalendarPage::new(CalendarRange::Week, day(2024, 5, 2), 0).weeks[0];
+    let lanes = week_lanes(&tasks, &week);
+    let lane_tasks: Vec<Vec<usize>> = lanes
+        .iter()
+        .map(|lane| lane.iter().map(|bar| bar.task).collect())
+        .collect();
+    // The bar continuing from the last week comes first and leaves space for
+    // the tasks later in the week
+    assert_eq!(vec![vec![1, 2, 0, 4]], lane_tasks);
+
+    let bar = &lanes[0][0];
+    assert_eq!((0, 1), (bar.first, bar.last));
+    assert!(bar.continues_before);
+    assert!(!bar.continues_after);
+    let bar = &lanes[0][3];
+    assert_eq!((5, 6), (bar.first, bar.last));
+    assert!(bar.continues_after);
+
+    // Tasks due on the same day need separate lanes
+    let same_day = vec![
+        task(None, local_time(2024, 5, 3)),
+        task(None, local_time(2024, 5, 3)),
+    ];
+    assert_eq!(2, week_lanes(&same_day, &week).len());
+}
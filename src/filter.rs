@@ -0,0 +1,106 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Days, Local, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::tasks::Task;
+
+pub use expression::Expression;
+
+mod expression;
+
+/// Quick filters for the due date of a task.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DueFilter {
+    #[default]
+    Any,
+    Overdue,
+    ThisWeek,
+    NoDueDate,
+}
+
+impl DueFilter {
+    pub const ALL: [DueFilter; 4] = [
+        DueFilter::Any,
+        DueFilter::Overdue,
+        DueFilter::ThisWeek,
+        DueFilter::NoDueDate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DueFilter::Any => "Any due date",
+            DueFilter::Overdue => "Overdue",
+            DueFilter::ThisWeek => "Due this week",
+            DueFilter::NoDueDate => "No due date",
+        }
+    }
+
+    fn matches(&self, due: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        match (self, due) {
+            (DueFilter::Any, _) => true,
+            (DueFilter::Overdue, Some(due)) => due <= now,
+            (DueFilter::ThisWeek, Some(due)) => {
+                // Weeks start on Monday in local time
+                let today = now.with_timezone(&Local).date_naive();
+                let week_start = today - Days::new(today.weekday().num_days_from_monday().into());
+                let week_end = week_start + Days::new(7);
+                let due = due.with_timezone(&Local).date_naive();
+                week_start <= due && due < week_end
+            }
+            (DueFilter::NoDueDate, due) => due.is_none(),
+            (_, None) => false,
+        }
+    }
+}
+
+/// Restricts which tasks are shown in the task list.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TaskFilter {
+    /// Filter expression, which can simply be words that all have to appear
+    /// in the title, project or description. See [`Expression`].
+    pub search: String,
+    pub source: Option<Uuid>,
+    pub project: Option<String>,
+    pub due: DueFilter,
+}
+
+impl TaskFilter {
+    /// True if any task could be hidden by this filter.
+    pub fn is_active(&self) -> bool {
+        self != &TaskFilter::default()
+    }
+
+    /// Parse the search text as filter expression.
+    pub fn expression(&self) -> Result<Expression> {
+        Expression::parse(&self.search)
+    }
+
+    /// Check the task against the quick filters and the already parsed
+    /// filter expression.
+    pub fn matches(&self, expression: &Expression, task: &Task, now: DateTime<Utc>) -> bool {
+        if self.source.is_some_and(|source| source != task.source_id) {
+            return false;
+        }
+        if self
+            .project
+            .as_ref()
+            .is_some_and(|project| project != &task.project)
+        {
+            return false;
+        }
+        self.due.matches(task.due, now) && expression.matches(task, now)
+    }
+}
+
+/// A filter that has been saved under a name.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SavedView {
+    pub name: String,
+    pub filter: TaskFilter,
+}
+
+#[cfg(test)]
+mod tests;
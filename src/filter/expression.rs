@@ -0,0 +1,396 @@
+//! A small language to filter tasks, e.g.
+//! `source:gitlab due<7d -label:blocked project:"infra*"`.
+//!
+//! An expression is a list of terms, which all have to match. A term is either
+//! a word that is searched in the title, project and description, or a
+//! condition `field:value`. Dates can also be compared with `<`, `<=`, `>` and
+//! `>=`. Terms starting with `-` are negated, and values with spaces can be
+//! quoted.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+
+use crate::tasks::Task;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A number of days relative to today or a calendar day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TimeValue {
+    Relative(TimeDelta),
+    Day(NaiveDate),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Title,
+    Source,
+    Project,
+    Label,
+    Kind,
+    Status,
+    Assignee,
+    Author,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateField {
+    Due,
+    Start,
+    Created,
+    Updated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    /// Search in title, project and description
+    Text(String),
+    /// Match a text field with a glob pattern
+    Field(TextField, String),
+    Priority(String),
+    Overdue,
+    Subtask,
+    Has(String),
+    NoDate(DateField),
+    Date(DateField, Operator, TimeValue),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+/// A parsed filter expression. The empty expression matches all tasks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expression {
+    terms: Vec<Term>,
+}
+
+/// Split the expression into terms. Whitespace inside quotes does not end a
+/// term, and the quotes are removed.
+fn split_terms(expression: &str) -> Result<Vec<String>> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in expression.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+            // Mark the quoted part, so operators in it are not recognized
+            current.push(c);
+        } else if c.is_whitespace() && !in_quotes {
+            if !current.is_empty() {
+                terms.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if in_quotes {
+        bail!("Missing closing quote in {current}");
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    Ok(terms)
+}
+
+/// Split a term at the first operator outside quotes.
+fn split_operator(term: &str) -> Option<(&str, Operator, &str)> {
+    let mut in_quotes = false;
+    for (i, c) in term.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' | '<' | '>' if !in_quotes => {
+                let rest = &term[i + 1..];
+                let (operator, value) = match (c, rest.strip_prefix('=')) {
+                    (':', _) => (Operator::Equal, rest),
+                    ('<', Some(value)) => (Operator::LessOrEqual, value),
+                    ('<', None) => (Operator::Less, rest),
+                    ('>', Some(value)) => (Operator::GreaterOrEqual, value),
+                    (_, _) => (Operator::Greater, rest),
+                };
+                return Some((&term[..i], operator, value));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unquote(value: &str) -> String {
+    value.replace('"', "")
+}
+
+fn parse_time(value: &str) -> Result<TimeValue> {
+    if value.eq_ignore_ascii_case("today") || value.eq_ignore_ascii_case("now") {
+        return Ok(TimeValue::Relative(TimeDelta::zero()));
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(TimeValue::Day(day));
+    }
+    let unit_start = value
+        .find(|c: char| c.is_alphabetic())
+        .with_context(|| format!("Invalid time '{value}', use e.g. 7d or 2024-05-31"))?;
+    let amount: i64 = value[..unit_start]
+        .parse()
+        .with_context(|| format!("Invalid number in '{value}'"))?;
+    let delta = match &value[unit_start..] {
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        unit => bail!("Unknown time unit '{unit}', use d or w"),
+    };
+    // Dates are compared as days, so the days before and after today have
+    // to exist
+    let today = Local::now().date_naive();
+    match delta {
+        Some(delta)
+            if today.checked_add_signed(delta).is_some()
+                && today.checked_sub_signed(delta).is_some() =>
+        {
+            Ok(TimeValue::Relative(delta))
+        }
+        _ => bail!("Time '{value}' is out of range"),
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term> {
+    let (negated, term) = match term.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, term),
+    };
+    let Some((field, operator, value)) = split_operator(term) else {
+        return Ok(Term {
+            negated,
+            condition: Condition::Text(unquote(term).to_lowercase()),
+        });
+    };
+    let value = unquote(value);
+    if value.is_empty() {
+        bail!("Missing value for '{field}'");
+    }
+    let field = field.to_lowercase();
+
+    let date_field = match field.as_str() {
+        "due" => Some(DateField::Due),
+        "start" => Some(DateField::Start),
+        "created" => Some(DateField::Created),
+        "updated" => Some(DateField::Updated),
+        _ => None,
+    };
+    if let Some(date_field) = date_field {
+        let condition = if operator == Operator::Equal && value.eq_ignore_ascii_case("none") {
+            Condition::NoDate(date_field)
+        } else {
+            Condition::Date(date_field, operator, parse_time(&value)?)
+        };
+        return Ok(Term { negated, condition });
+    }
+    if operator != Operator::Equal {
+        bail!("'{field}' can't be compared, only due, start, created and updated can");
+    }
+
+    let value = value.to_lowercase();
+    let condition = match field.as_str() {
+        "title" => Condition::Field(TextField::Title, value),
+        "source" => Condition::Field(TextField::Source, value),
+        "project" => Condition::Field(TextField::Project, value),
+        "label" => Condition::Field(TextField::Label, value),
+        "kind" | "type" => Condition::Field(TextField::Kind, value),
+        "status" => Condition::Field(TextField::Status, value),
+        "assignee" => Condition::Field(TextField::Assignee, value),
+        "author" => Condition::Field(TextField::Author, value),
+        "priority" => match value.as_str() {
+            "high" | "medium" | "low" => Condition::Priority(value),
+            _ => bail!("Unknown priority '{value}', use high, medium or low"),
+        },
+        "is" => match value.as_str() {
+            "overdue" => Condition::Overdue,
+            "subtask" => Condition::Subtask,
+            _ => bail!("Unknown value 'is:{value}', use overdue or subtask"),
+        },
+        "has" => match value.as_str() {
+            "due" | "start" | "label" | "assignee" | "url" | "description" => Condition::Has(value),
+            _ => bail!("Unknown value 'has:{value}'"),
+        },
+        _ => bail!("Unknown field '{field}'"),
+    };
+    Ok(Term { negated, condition })
+}
+
+/// Match a lower case text with a lower case pattern, in which `*` stands for
+/// any number of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Kind names without spaces, e.g. "pullrequest", and short aliases.
+fn kind_matches(pattern: &str, task: &Task) -> bool {
+    let kind = task.kind.name().to_lowercase().replace(' ', "");
+    let pattern = pattern.replace(['-', '_', ' '], "");
+    let alias = match kind.as_str() {
+        "pullrequest" => "pr",
+        "mergerequest" => "mr",
+        "reviewrequest" => "review",
+        "workpackage" => "wp",
+        _ => "",
+    };
+    glob_match(&pattern, &kind) || pattern == alias
+}
+
+fn date_value(task: &Task, field: DateField) -> Option<DateTime<Utc>> {
+    match field {
+        DateField::Due => task.due,
+        DateField::Start => task.start,
+        DateField::Created => task.created,
+        DateField::Updated => task.updated,
+    }
+}
+
+fn compare_date(
+    field: DateField,
+    date: DateTime<Utc>,
+    operator: Operator,
+    value: &TimeValue,
+    now: DateTime<Utc>,
+) -> bool {
+    let (date, bound, operator) = match value {
+        TimeValue::Day(day) => {
+            // Compare calendar days in local time
+            (date.with_timezone(&Local).date_naive(), *day, operator)
+        }
+        TimeValue::Relative(delta) => {
+            let today = now.with_timezone(&Local).date_naive();
+            let date = date.with_timezone(&Local).date_naive();
+            if matches!(field, DateField::Created | DateField::Updated) {
+                // For the past, the value is an age: "created<7d" are tasks
+                // that have been created less than 7 days ago
+                let Some(bound) = today.checked_sub_signed(*delta) else {
+                    return false;
+                };
+                let operator = match operator {
+                    Operator::Less => Operator::Greater,
+                    Operator::LessOrEqual => Operator::GreaterOrEqual,
+                    Operator::Greater => Operator::Less,
+                    Operator::GreaterOrEqual => Operator::LessOrEqual,
+                    Operator::Equal => Operator::Equal,
+                };
+                (date, bound, operator)
+            } else {
+                let Some(bound) = today.checked_add_signed(*delta) else {
+                    return false;
+                };
+                (date, bound, operator)
+            }
+        }
+    };
+    match operator {
+        Operator::Equal => date == bound,
+        Operator::Less => date < bound,
+        Operator::LessOrEqual => date <= bound,
+        Operator::Greater => date > bound,
+        Operator::GreaterOrEqual => date >= bound,
+    }
+}
+
+impl Condition {
+    fn matches(&self, task: &Task, now: DateTime<Utc>) -> bool {
+        let lower = |s: &str| s.to_lowercase();
+        match self {
+            Condition::Text(text) => {
+                lower(&task.title).contains(text)
+                    || lower(&task.project).contains(text)
+                    || lower(&task.description).contains(text)
+            }
+            Condition::Field(field, pattern) => match field {
+                TextField::Title => glob_match(&format!("*{pattern}*"), &lower(&task.title)),
+                TextField::Source => {
+                    glob_match(pattern, &lower(&task.source_name))
+                        || task
+                            .source_kind
+                            .is_some_and(|k| glob_match(pattern, k.slug()))
+                }
+                TextField::Project => glob_match(pattern, &lower(&task.project)),
+                TextField::Label => task.labels.iter().any(|l| glob_match(pattern, &lower(l))),
+                TextField::Kind => kind_matches(pattern, task),
+                TextField::Status => task
+                    .status
+                    .as_ref()
+                    .is_some_and(|s| glob_match(pattern, &lower(s))),
+                TextField::Assignee => task
+                    .assignees
+                    .iter()
+                    .any(|a| glob_match(pattern, &lower(a))),
+                TextField::Author => task
+                    .author
+                    .as_ref()
+                    .is_some_and(|a| glob_match(pattern, &lower(a))),
+            },
+            Condition::Priority(priority) => {
+                let actual = match task.priority {
+                    Some(1..=4) => "high",
+                    Some(5) => "medium",
+                    Some(_) => "low",
+                    None => return false,
+                };
+                actual == priority
+            }
+            Condition::Overdue => task.due.is_some_and(|due| due <= now),
+            Condition::Subtask => task.parent.is_some(),
+            Condition::Has(field) => match field.as_str() {
+                "due" => task.due.is_some(),
+                "start" => task.start.is_some(),
+                "label" => !task.labels.is_empty(),
+                "assignee" => !task.assignees.is_empty(),
+                "url" => task.url.is_some(),
+                "description" => !task.description.trim().is_empty(),
+                _ => false,
+            },
+            Condition::NoDate(field) => date_value(task, *field).is_none(),
+            Condition::Date(field, operator, value) => date_value(task, *field)
+                .is_some_and(|date| compare_date(*field, date, *operator, value, now)),
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let terms = split_terms(expression)?
+            .iter()
+            .map(|t| parse_term(t))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Expression { terms })
+    }
+
+    pub fn matches(&self, task: &Task, now: DateTime<Utc>) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.condition.matches(task, now) != term.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests;
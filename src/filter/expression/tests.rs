@@ -0,0 +1,122 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use crate::{
+    sources::SourceKind,
+    tasks::{Task, TaskKind},
+};
+
+use super::{glob_match, Expression};
+
+fn example_task() -> Task {
+    let now = Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+    Task {
+        project: "infra/servers".to_string(),
+        title: "Renew certificates".to_string(),
+        description: "For the mail server".to_string(),
+        source_name: "Work GitLab".to_string(),
+        source_kind: Some(SourceKind::GitLab),
+        kind: TaskKind::MergeRequest,
+        due: Some(now + TimeDelta::days(3)),
+        created: Some(now - TimeDelta::days(10)),
+        labels: vec!["ops".to_string(), "blocked".to_string()],
+        assignees: vec!["alice".to_string()],
+        priority: Some(2),
+        ..Default::default()
+    }
+}
+
+fn matches(expression: &str) -> bool {
+    let now = Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+    Expression::parse(expression)
+        .unwrap()
+        .matches(&example_task(), now)
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("infra*", "infra/servers"));
+    assert!(glob_match("*servers", "infra/servers"));
+    assert!(glob_match("in*/*ers", "infra/servers"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("ops", "ops"));
+    assert!(!glob_match("ops", "devops"));
+    assert!(!glob_match("a*a", "a"));
+}
+
+#[test]
+fn test_empty_expression() {
+    assert_eq!(Expression::default(), Expression::parse("  ").unwrap());
+    assert!(matches(""));
+}
+
+#[test]
+fn test_text_and_fields() {
+    assert!(matches("renew MAIL"));
+    assert!(!matches("renew web"));
+    assert!(matches("source:gitlab"));
+    assert!(matches("source:\"work gitlab\""));
+    assert!(!matches("source:github"));
+    assert!(matches("project:\"infra*\""));
+    assert!(!matches("project:infra"));
+    assert!(matches("label:blocked"));
+    assert!(matches("kind:mr"));
+    assert!(matches("kind:merge-request"));
+    assert!(matches("assignee:alice"));
+    assert!(matches("priority:high"));
+    assert!(matches("title:certificate"));
+    assert!(matches("has:label -has:url"));
+}
+
+#[test]
+fn test_negation() {
+    assert!(!matches("-label:blocked"));
+    assert!(matches("-label:waiting"));
+    assert!(!matches("source:gitlab -renew"));
+}
+
+#[test]
+fn test_dates() {
+    assert!(matches("due<7d"));
+    assert!(!matches("due<2d"));
+    assert!(matches("due>=3d"));
+    assert!(matches("due<2024-06-01"));
+    assert!(matches("due:2024-05-18"));
+    assert!(!matches("due:none"));
+    assert!(matches("start:none"));
+    assert!(!matches("is:overdue"));
+    // Relative times of past dates are an age
+    assert!(matches("created>1w"));
+    assert!(!matches("created<1w"));
+}
+
+#[test]
+fn test_example_from_documentation() {
+    assert!(!matches(
+        "source:gitlab due<7d -label:blocked project:\"infra*\""
+    ));
+    assert!(matches(
+        "source:gitlab due<7d -label:waiting project:\"infra*\""
+    ));
+}
+
+#[test]
+fn test_parse_errors() {
+    for invalid in [
+        "unknown:value",
+        "label<3",
+        "due<soon",
+        "due<3y",
+        // Dates are compared as days
+        "updated<12h",
+        "due<99999999d",
+        "created>-99999999d",
+        "priority:urgent",
+        "project:\"infra",
+        "label:",
+    ] {
+        assert!(
+            Expression::parse(invalid).is_err(),
+            "{invalid} should not be valid"
+        );
+    }
+}
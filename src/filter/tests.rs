@@ -0,0 +1,86 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::tasks::Task;
+
+use super::{DueFilter, Expression, TaskFilter};
+
+#[test]
+fn test_search_words() {
+    let task = Task {
+        project: "Infrastructure".to_string(),
+        title: "Renew certificates".to_string(),
+        description: "For the mail server".to_string(),
+        ..Default::default()
+    };
+    let now = Utc::now();
+    let matches = |search: &str| {
+        let filter = TaskFilter {
+            search: search.to_string(),
+            ..Default::default()
+        };
+        filter.matches(&filter.expression().unwrap(), &task, now)
+    };
+    assert!(matches(""));
+    assert!(matches("renew"));
+    assert!(matches("infra MAIL"));
+    assert!(!matches("renew web"));
+}
+
+#[test]
+fn test_source_and_project() {
+    let source_id = Uuid::from_u128(1);
+    let task = Task {
+        project: "work".to_string(),
+        source_id,
+        ..Default::default()
+    };
+    let now = Utc::now();
+    let no_expression = Expression::default();
+    let filter = TaskFilter {
+        source: Some(source_id),
+        project: Some("work".to_string()),
+        ..Default::default()
+    };
+    assert!(filter.is_active());
+    assert!(filter.matches(&no_expression, &task, now));
+    let other_source = TaskFilter {
+        source: Some(Uuid::from_u128(2)),
+        ..Default::default()
+    };
+    assert!(!other_source.matches(&no_expression, &task, now));
+    let other_project = TaskFilter {
+        project: Some("home".to_string()),
+        ..Default::default()
+    };
+    assert!(!other_project.matches(&no_expression, &task, now));
+}
+
+#[test]
+fn test_due_filter() {
+    // A Wednesday, so the days around it are in the same week in any time zone
+    let now = Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+    let task_due = |due: Option<TimeDelta>| Task {
+        due: due.map(|d| now + d),
+        ..Default::default()
+    };
+    let overdue = task_due(Some(TimeDelta::hours(-2)));
+    let tomorrow = task_due(Some(TimeDelta::days(1)));
+    let next_month = task_due(Some(TimeDelta::days(30)));
+    let undated = task_due(None);
+
+    let no_expression = Expression::default();
+    let filter = |due| TaskFilter {
+        due,
+        ..Default::default()
+    };
+    assert!(!filter(DueFilter::Any).is_active());
+    assert!(filter(DueFilter::Overdue).matches(&no_expression, &overdue, now));
+    assert!(!filter(DueFilter::Overdue).matches(&no_expression, &tomorrow, now));
+    assert!(!filter(DueFilter::Overdue).matches(&no_expression, &undated, now));
+    assert!(filter(DueFilter::ThisWeek).matches(&no_expression, &overdue, now));
+    assert!(filter(DueFilter::ThisWeek).matches(&no_expression, &tomorrow, now));
+    assert!(!filter(DueFilter::ThisWeek).matches(&no_expression, &next_month, now));
+    assert!(filter(DueFilter::NoDueDate).matches(&no_expression, &undated, now));
+    assert!(!filter(DueFilter::NoDueDate).matches(&no_expression, &tomorrow, now));
+}
@@ -0,0 +1,136 @@
+use chrono::{DateTime, Datelike, Days, Local, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::tasks::Task;
+
+/// How the task list is divided into sections.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Grouping {
+    #[default]
+    None,
+    Project,
+    Source,
+    Due,
+}
+
+impl Grouping {
+    pub const ALL: [Grouping; 4] = [
+        Grouping::None,
+        Grouping::Project,
+        Grouping::Source,
+        Grouping::Due,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grouping::None => "No grouping",
+            Grouping::Project => "Project",
+            Grouping::Source => "Source",
+            Grouping::Due => "Due date",
+        }
+    }
+}
+
+/// Sections of the task list when grouping by due date, in the order they are
+/// shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DueBucket {
+    Overdue,
+    Today,
+    Tomorrow,
+    ThisWeek,
+    Later,
+    NoDate,
+}
+
+impl DueBucket {
+    pub fn of(due: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Self {
+        let Some(due) = due else {
+            return DueBucket::NoDate;
+        };
+        if due <= now {
+            return DueBucket::Overdue;
+        }
+        let today = now.with_timezone(&Local).date_naive();
+        let due = due.with_timezone(&Local).date_naive();
+        // Weeks start on Monday
+        let next_week = today + Days::new((7 - today.weekday().num_days_from_monday()).into());
+        if due == today {
+            DueBucket::Today
+        } else if due == today + Days::new(1) {
+            DueBucket::Tomorrow
+        } else if due < next_week {
+            DueBucket::ThisWeek
+        } else {
+            DueBucket::Later
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DueBucket::Overdue => "Overdue",
+            DueBucket::Today => "Today",
+            DueBucket::Tomorrow => "Tomorrow",
+            DueBucket::ThisWeek => "This week",
+            DueBucket::Later => "Later",
+            DueBucket::NoDate => "No date",
+        }
+    }
+}
+
+/// A section of the task list.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub tasks: Vec<Task>,
+}
+
+/// Divide the tasks into groups. The tasks keep their order within each
+/// group. Projects are ordered by name, sources in the given order and due
+/// dates by the buckets.
+pub fn group_tasks(
+    tasks: Vec<Task>,
+    grouping: Grouping,
+    source_order: &[Uuid],
+    now: DateTime<Utc>,
+) -> Vec<Group> {
+    // Sort key and name of the group for each task
+    let key = |task: &Task| -> (usize, String) {
+        match grouping {
+            Grouping::None => (0, String::default()),
+            Grouping::Project => (0, task.project.clone()),
+            Grouping::Source => {
+                let position = source_order
+                    .iter()
+                    .position(|s| *s == task.source_id)
+                    .unwrap_or(source_order.len());
+                (position, task.source_name.clone())
+            }
+            Grouping::Due => {
+                let bucket = DueBucket::of(task.due, now);
+                (bucket as usize, bucket.name().to_string())
+            }
+        }
+    };
+
+    let mut groups: Vec<((usize, String), Group)> = Vec::new();
+    for task in tasks {
+        let key = key(&task);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.tasks.push(task),
+            None => groups.push((
+                key.clone(),
+                Group {
+                    name: key.1,
+                    tasks: vec![task],
+                },
+            )),
+        }
+    }
+    groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+#[cfg(test)]
+mod tests;
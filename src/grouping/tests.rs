@@ -0,0 +1,84 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::tasks::Task;
+
+use super::{group_tasks, DueBucket, Grouping};
+
+fn names(groups: &[super::Group]) -> Vec<(&str, usize)> {
+    groups
+        .iter()
+        .map(|g| (g.name.as_str(), g.tasks.len()))
+        .collect()
+}
+
+#[test]
+fn test_group_by_project() {
+    let tasks = ["work", "home", "work"].map(|project| Task {
+        project: project.to_string(),
+        title: project.to_string(),
+        ..Default::default()
+    });
+    let groups = group_tasks(tasks.to_vec(), Grouping::Project, &[], Utc::now());
+    assert_eq!(vec![("home", 1), ("work", 2)], names(&groups));
+
+    let groups = group_tasks(tasks.to_vec(), Grouping::None, &[], Utc::now());
+    assert_eq!(1, groups.len());
+    let titles: Vec<_> = groups[0].tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(vec!["work", "home", "work"], titles);
+}
+
+#[test]
+fn test_group_by_source() {
+    let first = Uuid::from_u128(1);
+    let second = Uuid::from_u128(2);
+    let task = |source_id, source_name: &str| Task {
+        source_id,
+        source_name: source_name.to_string(),
+        ..Default::default()
+    };
+    let tasks = vec![
+        task(second, "Zulu"),
+        task(Uuid::from_u128(3), "Removed"),
+        task(first, "Alpha"),
+        task(second, "Zulu"),
+    ];
+    let groups = group_tasks(tasks, Grouping::Source, &[second, first], Utc::now());
+    assert_eq!(
+        vec![("Zulu", 2), ("Alpha", 1), ("Removed", 1)],
+        names(&groups)
+    );
+}
+
+#[test]
+fn test_due_buckets() {
+    // A Wednesday at noon, so adding whole days keeps the weekday in all time
+    // zones
+    let now = Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+    let bucket = |days: Option<i64>| DueBucket::of(days.map(|d| now + TimeDelta::days(d)), now);
+    assert_eq!(DueBucket::Overdue, bucket(Some(-1)));
+    assert_eq!(DueBucket::Tomorrow, bucket(Some(1)));
+    assert_eq!(DueBucket::ThisWeek, bucket(Some(3)));
+    assert_eq!(DueBucket::Later, bucket(Some(14)));
+    assert_eq!(DueBucket::NoDate, bucket(None));
+    assert_eq!(
+        DueBucket::Today,
+        DueBucket::of(Some(now + TimeDelta::minutes(1)), now)
+    );
+
+    let tasks = [Some(14), None, Some(-1), Some(1)].map(|days| Task {
+        due: days.map(|d| now + TimeDelta::days(d)),
+        title: format!("{days:?}"),
+        ..Default::default()
+    });
+    let groups = group_tasks(tasks.to_vec(), Grouping::Due, &[], now);
+    assert_eq!(
+        vec![
+            ("Overdue", 1),
+            ("Tomorrow", 1),
+            ("Later", 1),
+            ("No date", 1)
+        ],
+        names(&groups)
+    );
+}
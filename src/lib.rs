@@ -1,6 +1,12 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod calendar;
+mod filter;
+mod grouping;
+mod markdown;
+pub mod secrets;
+mod sorting;
 pub mod sources;
 pub mod tasks;
 pub use app::TaskPickerApp;
@@ -0,0 +1,207 @@
+//! Minimal Markdown support for task descriptions. Only the inline formatting
+//! and block elements that are common in issue descriptions are recognized,
+//! everything else is shown as it is.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub strong: bool,
+    pub emphasis: bool,
+    pub code: bool,
+}
+
+/// A piece of text with the same formatting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// Append text to the spans, merging it with the last span if the style is
+/// the same.
+fn push(spans: &mut Vec<Span>, text: &str, style: &Style) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(last) = spans.last_mut() {
+        if &last.style == style {
+            last.text.push_str(text);
+            return;
+        }
+    }
+    spans.push(Span {
+        text: text.to_string(),
+        style: style.clone(),
+    });
+}
+
+/// Parse a link like `[label](url)` at the start of the text and return the
+/// label and the length of the whole link.
+fn link(text: &str) -> Option<(&str, usize)> {
+    let label_end = text.strip_prefix('[')?.find(']')? + 1;
+    let url_len = text[label_end + 1..].strip_prefix('(')?.find(')')?;
+    Some((&text[1..label_end], label_end + url_len + 3))
+}
+
+fn parse_inline(text: &str, base: &Style, spans: &mut Vec<Span>) {
+    let mut style = base.clone();
+    let mut plain = String::new();
+    let mut previous = None;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        if c == '\\' {
+            if let Some(escaped) = after.chars().next().filter(|e| e.is_ascii_punctuation()) {
+                plain.push(escaped);
+                previous = Some(escaped);
+                rest = &after[1..];
+                continue;
+            }
+        } else if c == '`' {
+            if let Some(end) = after.find('`') {
+                push(spans, &std::mem::take(&mut plain), &style);
+                let code = Style {
+                    code: true,
+                    ..style.clone()
+                };
+                push(spans, &after[..end], &code);
+                previous = Some('`');
+                rest = &after[end + 1..];
+                continue;
+            }
+        } else if c == '!' && after.starts_with('[') {
+            // Images are replaced by their alternative text
+            if let Some((alt, len)) = link(after) {
+                plain.push_str(alt);
+                previous = alt.chars().last();
+                rest = &after[len..];
+                continue;
+            }
+        } else if c == '[' {
+            if let Some((label, len)) = link(rest) {
+                push(spans, &std::mem::take(&mut plain), &style);
+                parse_inline(label, &style, spans);
+                previous = label.chars().last();
+                rest = &rest[len..];
+                continue;
+            }
+        } else if let Some(marker) = ["**", "__", "*", "_"]
+            .into_iter()
+            .find(|m| rest.starts_with(m))
+        {
+            let strong = marker.len() == 2;
+            let active = if strong { style.strong } else { style.emphasis };
+            let after = &rest[marker.len()..];
+            // Underscores within words, e.g. in snake_case, are no emphasis
+            let intraword =
+                marker.starts_with('_') && previous.is_some_and(|p: char| p.is_alphanumeric());
+            let opens = !intraword
+                && after.chars().next().is_some_and(|n| !n.is_whitespace())
+                && after.contains(marker);
+            if active || opens {
+                push(spans, &std::mem::take(&mut plain), &style);
+                if strong {
+                    style.strong = !active;
+                } else {
+                    style.emphasis = !active;
+                }
+                rest = after;
+                continue;
+            }
+        }
+        plain.push(c);
+        previous = Some(c);
+        rest = after;
+    }
+    push(spans, &plain, &style);
+}
+
+/// Return the text of a heading like `## Title`.
+fn heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    if (1..=6).contains(&level) && (text.is_empty() || text.starts_with(' ')) {
+        Some(text.trim())
+    } else {
+        None
+    }
+}
+
+/// True for horizontal rules like `---` or `***`.
+fn is_rule(line: &str) -> bool {
+    let mut chars = line.chars().filter(|c| !c.is_whitespace());
+    match chars.next() {
+        Some(first @ ('-' | '*' | '_')) => {
+            let rest: Vec<_> = chars.collect();
+            rest.len() >= 2 && rest.iter().all(|c| *c == first)
+        }
+        _ => false,
+    }
+}
+
+/// Parse Markdown text into formatted spans. Line breaks are kept as they are.
+pub fn parse(markdown: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut in_code_block = false;
+    let mut first_line = true;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if !in_code_block && is_rule(trimmed) {
+            continue;
+        }
+        if !first_line {
+            push(&mut spans, "\n", &Style::default());
+        }
+        first_line = false;
+
+        if in_code_block {
+            let code = Style {
+                code: true,
+                ..Default::default()
+            };
+            push(&mut spans, line, &code);
+        } else if let Some(heading) = heading(trimmed) {
+            let strong = Style {
+                strong: true,
+                ..Default::default()
+            };
+            parse_inline(heading, &strong, &mut spans);
+        } else if let Some(item) = ["- ", "* ", "+ "]
+            .into_iter()
+            .find_map(|bullet| trimmed.strip_prefix(bullet))
+        {
+            push(&mut spans, "• ", &Style::default());
+            parse_inline(item, &Style::default(), &mut spans);
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            parse_inline(quote.trim_start(), &Style::default(), &mut spans);
+        } else {
+            parse_inline(trimmed, &Style::default(), &mut spans);
+        }
+    }
+    spans
+}
+
+/// Limit the spans to the given number of characters, and add `...` if text
+/// has been removed.
+pub fn truncate(spans: Vec<Span>, max_chars: usize) -> Vec<Span> {
+    let mut remaining = max_chars;
+    let mut result = Vec::new();
+    for mut span in spans {
+        let len = span.text.chars().count();
+        if len > remaining {
+            span.text = span.text.chars().take(remaining).collect();
+            span.text.push_str("...");
+            result.push(span);
+            break;
+        }
+        remaining -= len;
+        result.push(span);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,78 @@
+use super::{parse, truncate, Span, Style};
+
+fn plain(text: &str) -> Span {
+    Span {
+        text: text.to_string(),
+        style: Style::default(),
+    }
+}
+
+fn styled(text: &str, strong: bool, emphasis: bool, code: bool) -> Span {
+    Span {
+        text: text.to_string(),
+        style: Style {
+            strong,
+            emphasis,
+            code,
+        },
+    }
+}
+
+#[test]
+fn parse_inline_formatting() {
+    assert_eq!(
+        vec![
+            plain("This is a "),
+            styled("description", false, true, false),
+            plain(" with "),
+            styled("bold", true, false, false),
+            plain(" and "),
+            styled("code", false, false, true),
+            plain("."),
+        ],
+        parse("This is a *description* with **bold** and `code`.")
+    );
+}
+
+#[test]
+fn parse_literal_markers() {
+    assert_eq!(
+        vec![plain("2 * 3 = 6, snake_case_name and *not emphasized")],
+        parse("2 * 3 = 6, snake_case_name and \\*not emphasized")
+    );
+}
+
+#[test]
+fn parse_links_and_images() {
+    assert_eq!(
+        vec![
+            plain("See "),
+            styled("the docs", true, false, false),
+            plain(" and Screenshot"),
+        ],
+        parse("See [**the docs**](https://example.com) and ![Screenshot](image.png)")
+    );
+}
+
+#[test]
+fn parse_blocks() {
+    let markdown = "# Summary\n\n- first\n- second\n\n---\n> quoted\n```\nlet x = 1;\n```";
+    assert_eq!(
+        vec![
+            styled("Summary", true, false, false),
+            plain("\n\n• first\n• second\n\nquoted\n"),
+            styled("let x = 1;", false, false, true),
+        ],
+        parse(markdown)
+    );
+}
+
+#[test]
+fn truncate_spans() {
+    let spans = parse("Some **important** text");
+    assert_eq!(
+        vec![plain("Some "), styled("imp...", true, false, false)],
+        truncate(spans.clone(), 8)
+    );
+    assert_eq!(spans, truncate(spans.clone(), 100));
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use base64::prelude::*;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use json::{object, JsonValue};
+use keyring::Entry;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::sources::TaskSource;
+
+const KEYRING_SERVICE: &str = "task-picker";
+const SALT_LEN: usize = 16;
+
+/// Prefix of the environment variables that can provide the secret of a
+/// source. The rest of the variable name is the source name in upper case,
+/// with all other characters than letters and digits replaced by `_`.
+pub const SECRET_ENV_PREFIX: &str = "TASK_PICKER_SECRET_";
+
+/// Environment variable with the passphrase of the encrypted secret file.
+pub const PASSPHRASE_ENV: &str = "TASK_PICKER_PASSPHRASE";
+
+/// Name of the environment variable for the secret of the given source name.
+pub fn secret_env_var(source_name: &str) -> String {
+    let name: String = source_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{SECRET_ENV_PREFIX}{name}")
+}
+
+/// Run the secret command for a source and return the first line of its
+/// output. The placeholders `{name}` and `{id}` in the arguments are replaced
+/// by the name and ID of the source. No shell is involved, so arguments can't
+/// be quoted.
+fn run_secret_command(command: &str, source_name: &str, source_id: Uuid) -> Result<String> {
+    let mut args = command
+        .split_whitespace()
+        .map(|a| {
+            a.replace("{name}", source_name)
+                .replace("{id}", &source_id.to_string())
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let program = args.next().context("Empty secret command")?;
+    let output = Command::new(&program).args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "Secret command {program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Could not derive key from passphrase: {e}"))?;
+    Ok(key)
+}
+
+/// The decrypted content of the secret file, together with the key derived
+/// from the passphrase and the salt it was derived with.
+struct UnlockedFile {
+    salt: Vec<u8>,
+    key: Key,
+    secrets: HashMap<Uuid, String>,
+}
+
+/// Derive the key for the secret file and decrypt it. A file that does not
+/// exist yet contains no secrets and gets a new salt.
+fn open_secret_file(path: &Path, passphrase: &str) -> Result<UnlockedFile> {
+    if !path.exists() {
+        let mut salt = vec![0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+        return Ok(UnlockedFile {
+            salt,
+            key,
+            secrets: HashMap::default(),
+        });
+    }
+    let file = json::parse(&std::fs::read_to_string(path)?)?;
+    let field = |name: &str| -> Result<Vec<u8>> {
+        let value = file[name]
+            .as_str()
+            .with_context(|| format!("Missing '{name}' in secret file"))?;
+        Ok(BASE64_STANDARD.decode(value)?)
+    };
+    let salt = field("salt")?;
+    let nonce = field("nonce")?;
+    if nonce.len() != 12 {
+        bail!("Invalid nonce in secret file");
+    }
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(&nonce), field("data")?.as_slice())
+        .map_err(|_| anyhow!("Wrong passphrase for the secret file"))?;
+
+    let mut secrets = HashMap::default();
+    for (id, secret) in json::parse(&String::from_utf8(plaintext)?)?.entries() {
+        if let (Ok(id), Some(secret)) = (Uuid::parse_str(id), secret.as_str()) {
+            secrets.insert(id, secret.to_string());
+        }
+    }
+    Ok(UnlockedFile { salt, key, secrets })
+}
+
+/// Encrypt and write the secrets with an already derived key, using a new
+/// nonce.
+fn write_encrypted(
+    path: &Path,
+    salt: &[u8],
+    key: &Key,
+    secrets: &HashMap<Uuid, String>,
+) -> Result<()> {
+    let mut plaintext = JsonValue::new_object();
+    for (id, secret) in secrets {
+        plaintext[id.to_string()] = secret.as_str().into();
+    }
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let data = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plaintext.dump().as_bytes())
+        .map_err(|_| anyhow!("Could not encrypt the secret file"))?;
+
+    let file = object! {
+        salt: BASE64_STANDARD.encode(salt),
+        nonce: BASE64_STANDARD.encode(nonce),
+        data: BASE64_STANDARD.encode(data),
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, file.dump())?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn read_secret_file(path: &Path, passphrase: &str) -> Result<HashMap<Uuid, String>> {
+    Ok(open_secret_file(path, passphrase)?.secrets)
+}
+
+#[cfg(test)]
+fn write_secret_file(path: &Path, passphrase: &str, secrets: &HashMap<Uuid, String>) -> Result<()> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    write_encrypted(path, &salt, &derive_key(passphrase, &salt)?, secrets)
+}
+
+#[derive(Default)]
+struct SecretFile {
+    path: Option<PathBuf>,
+    /// Checked when the path is set and updated when writing the file, so
+    /// the UI does not need to access the file system for every frame.
+    exists: bool,
+    unlocked: Option<UnlockedFile>,
+}
+
+/// Output of the secret command for a source, which is only run once per
+/// session.
+struct CommandOutput {
+    command: String,
+    source_name: String,
+    secret: String,
+}
+
+/// Provides the secrets (passwords or tokens) of the sources. Secrets are
+/// looked up in this order:
+///
+/// 1. an environment variable (see [`secret_env_var`]),
+/// 2. the output of a configurable command, e.g. `pass show task-picker/{name}`,
+/// 3. the keyring of the operating system,
+/// 4. a file encrypted with a master passphrase, which is used instead of the
+///    keyring when the keyring is not available.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SecretStore {
+    /// Command that prints the secret of a source. Not used when empty.
+    pub command: String,
+    #[serde(skip)]
+    file: Arc<Mutex<SecretFile>>,
+    #[serde(skip)]
+    command_outputs: Arc<Mutex<HashMap<Uuid, CommandOutput>>>,
+}
+
+impl SecretStore {
+    /// Set the location of the encrypted secret file. If the passphrase is
+    /// given as environment variable, the file is unlocked right away.
+    pub fn set_file(&self, path: Option<PathBuf>) {
+        {
+            let mut file = self.file.lock().expect("Lock poisoning");
+            file.exists = path.as_ref().is_some_and(|p| p.exists());
+            file.path = path;
+            file.unlocked = None;
+        }
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            if let Err(e) = self.unlock(&passphrase) {
+                warn!("Could not unlock secret file with {PASSPHRASE_ENV}: {e}");
+            }
+        }
+    }
+
+    /// True if there is a secret file that has not been unlocked yet.
+    pub fn is_locked(&self) -> bool {
+        let file = self.file.lock().expect("Lock poisoning");
+        file.unlocked.is_none() && file.exists
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.file.lock().expect("Lock poisoning").unlocked.is_some()
+    }
+
+    fn has_file(&self) -> bool {
+        self.file.lock().expect("Lock poisoning").exists
+    }
+
+    /// Use the given master passphrase for the secret file. Fails if a secret
+    /// file exists, but can't be decrypted with this passphrase. The key is
+    /// only derived here and kept in memory until the file is locked again.
+    pub fn unlock(&self, passphrase: &str) -> Result<()> {
+        let path = self.file.lock().expect("Lock poisoning").path.clone();
+        let Some(path) = path else {
+            bail!("There is no location for the secret file");
+        };
+        let unlocked = open_secret_file(&path, passphrase)?;
+        let mut file = self.file.lock().expect("Lock poisoning");
+        if file.path.as_ref() == Some(&path) {
+            file.unlocked = Some(unlocked);
+        }
+        Ok(())
+    }
+
+    /// Forget the key and the decrypted secrets of the secret file.
+    pub fn lock(&self) {
+        self.file.lock().expect("Lock poisoning").unlocked = None;
+    }
+
+    /// Read-modify-write access to the secrets of the unlocked secret file.
+    fn update_file<F>(&self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut HashMap<Uuid, String>),
+    {
+        let mut file = self.file.lock().expect("Lock poisoning");
+        let SecretFile {
+            path: Some(path),
+            exists,
+            unlocked: Some(unlocked),
+        } = &mut *file
+        else {
+            bail!("The keyring is not available. Set a master passphrase to store secrets in an encrypted file instead.");
+        };
+        let mut secrets = unlocked.secrets.clone();
+        update(&mut secrets);
+        write_encrypted(path, &unlocked.salt, &unlocked.key, &secrets)?;
+        unlocked.secrets = secrets;
+        *exists = true;
+        Ok(())
+    }
+
+    fn file_secret(&self, source_id: Uuid) -> Option<String> {
+        let file = self.file.lock().expect("Lock poisoning");
+        file.unlocked.as_ref()?.secrets.get(&source_id).cloned()
+    }
+
+    /// Output of the secret command for the source. The command is only run
+    /// again when it or the name of the source changed.
+    fn command_secret(&self, source: &TaskSource) -> Option<String> {
+        if self.command.trim().is_empty() {
+            return None;
+        }
+        if let Some(output) = self
+            .command_outputs
+            .lock()
+            .expect("Lock poisoning")
+            .get(&source.id())
+            .filter(|o| o.command == self.command && o.source_name == source.name())
+        {
+            return Some(output.secret.clone());
+        }
+        match run_secret_command(&self.command, source.name(), source.id()) {
+            Ok(secret) if !secret.is_empty() => {
+                self.command_outputs.lock().expect("Lock poisoning").insert(
+                    source.id(),
+                    CommandOutput {
+                        command: self.command.clone(),
+                        source_name: source.name().to_string(),
+                        secret: secret.clone(),
+                    },
+                );
+                Some(secret)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Could not get secret for {}: {e}", source.name());
+                None
+            }
+        }
+    }
+
+    /// Secret provided by the environment or the secret command, which are
+    /// never stored by Task Picker itself.
+    fn external_secret(&self, source: &TaskSource) -> Option<String> {
+        if let Ok(secret) = std::env::var(secret_env_var(source.name())) {
+            return Some(secret);
+        }
+        self.command_secret(source)
+    }
+
+    /// Whether the secret of this source is provided by the environment or the
+    /// secret command, so it can't be changed by Task Picker.
+    pub fn is_external(&self, source: &TaskSource) -> bool {
+        self.external_secret(source).is_some()
+    }
+
+    /// Returns the secret (e.g. a password or a token) for this task source.
+    pub fn get(&self, source: &TaskSource) -> Option<String> {
+        if let Some(secret) = self.external_secret(source) {
+            return Some(secret);
+        }
+        if let Ok(secret) =
+            Entry::new(KEYRING_SERVICE, &source.id().to_string()).and_then(|e| e.get_password())
+        {
+            return Some(secret);
+        }
+        self.file_secret(source.id())
+    }
+
+    /// Store the secret for a source in the keyring, or in the encrypted file
+    /// if the keyring is not available. Secrets that are provided by the
+    /// environment or the secret command are not stored.
+    pub fn set(&self, source: &TaskSource, secret: &str) -> Result<()> {
+        if self.external_secret(source).as_deref() == Some(secret) {
+            return Ok(());
+        }
+        let source_id = source.id();
+        let keyring_result = Entry::new(KEYRING_SERVICE, &source_id.to_string())
+            .and_then(|e| e.set_password(secret));
+        match keyring_result {
+            Ok(()) => {
+                if self.is_unlocked() && self.has_file() {
+                    self.update_file(|secrets| {
+                        secrets.remove(&source_id);
+                    })?;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Could not store secret in the keyring: {e}");
+                self.update_file(|secrets| {
+                    secrets.insert(source_id, secret.to_string());
+                })
+            }
+        }
+    }
+
+    /// Remove the secret of a source from the keyring and the secret file. A
+    /// missing entry is not an error.
+    pub fn delete(&self, source_id: Uuid) -> Result<()> {
+        let keyring_result = match Entry::new(KEYRING_SERVICE, &source_id.to_string())
+            .and_then(|e| e.delete_password())
+        {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        };
+        self.delete_from_file(source_id, keyring_result)
+    }
+
+    /// Remove the secret of a source from the secret file, if it is unlocked,
+    /// and combine the result with the one of removing it from the keyring.
+    /// A locked file can't be changed and is skipped.
+    fn delete_from_file(&self, source_id: Uuid, keyring_result: Result<()>) -> Result<()> {
+        let file_result = if self.is_unlocked() && self.has_file() {
+            self.update_file(|secrets| {
+                secrets.remove(&source_id);
+            })
+        } else {
+            Ok(())
+        };
+        match (keyring_result, file_result) {
+            (Err(e), Err(file_error)) => Err(anyhow!(
+                "{e}, and could not update the secret file: {file_error}"
+            )),
+            (keyring_result, file_result) => keyring_result.and(file_result),
+        }
+    }
+
+    /// Older versions stored the secret under the name of the source. Move
+    /// it to the entry for the source ID, unless there already is a secret
+    /// for this ID. Returns true if there was a secret to move.
+    pub fn migrate_legacy(&self, source: &TaskSource) -> Result<bool> {
+        let legacy_entry = Entry::new(KEYRING_SERVICE, source.name())?;
+        let secret = match legacy_entry.get_password() {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let entry = Entry::new(KEYRING_SERVICE, &source.id().to_string())?;
+        match entry.get_password() {
+            Ok(_) => {}
+            Err(keyring::Error::NoEntry) => entry.set_password(&secret)?,
+            Err(e) => return Err(e.into()),
+        }
+        match legacy_entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
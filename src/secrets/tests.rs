@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use uuid::Uuid;
+
+use crate::sources::{GitHubSource, TaskSource};
+
+use super::{read_secret_file, run_secret_command, secret_env_var, write_secret_file, SecretStore};
+
+#[test]
+fn test_secret_env_var() {
+    assert_eq!("TASK_PICKER_SECRET_GITHUB", secret_env_var("GitHub"));
+    assert_eq!(
+        "TASK_PICKER_SECRET_MY_CALENDAR__WORK_",
+        secret_env_var("My Calendar (work)")
+    );
+}
+
+#[test]
+fn test_secret_file_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.json");
+    let id = Uuid::from_u128(1);
+
+    let secrets = HashMap::from([(id, "token".to_string())]);
+    write_secret_file(&path, "correct horse", &secrets).unwrap();
+    // The secret is not stored in plain text
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("token"));
+
+    let loaded = read_secret_file(&path, "correct horse").unwrap();
+    assert_eq!(Some("token"), loaded.get(&id).map(|s| s.as_str()));
+    assert!(read_secret_file(&path, "wrong").is_err());
+}
+
+#[test]
+fn test_unlock_secret_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.json");
+    let store = SecretStore::default();
+    store.set_file(Some(path.clone()));
+    assert!(!store.is_locked());
+
+    store.unlock("correct horse").unwrap();
+    store
+        .update_file(|secrets| {
+            secrets.insert(Uuid::from_u128(1), "token".to_string());
+        })
+        .unwrap();
+    assert_eq!(
+        Some("token".to_string()),
+        store.file_secret(Uuid::from_u128(1))
+    );
+
+    let store = SecretStore::default();
+    store.set_file(Some(path));
+    assert!(store.is_locked());
+    assert!(store.unlock("wrong").is_err());
+    assert!(store.is_locked());
+    store.unlock("correct horse").unwrap();
+    assert!(!store.is_locked());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_secret_command() {
+    let secret = run_secret_command("echo secret-{name}", "GitHub", Uuid::from_u128(1)).unwrap();
+    assert_eq!("secret-GitHub", secret);
+    assert!(run_secret_command("false", "GitHub", Uuid::from_u128(1)).is_err());
+}
+
+#[test]
+fn test_lock_secret_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.json");
+    let store = SecretStore::default();
+    store.set_file(Some(path.clone()));
+    store.unlock("correct horse").unwrap();
+    store
+        .update_file(|secrets| {
+            secrets.insert(Uuid::from_u128(1), "token".to_string());
+        })
+        .unwrap();
+    // Writing with the cached key keeps the file readable with the passphrase
+    let loaded = read_secret_file(&path, "correct horse").unwrap();
+    assert_eq!(
+        Some("token"),
+        loaded.get(&Uuid::from_u128(1)).map(|s| s.as_str())
+    );
+
+    store.lock();
+    assert!(store.is_locked());
+    assert_eq!(None, store.file_secret(Uuid::from_u128(1)));
+    assert!(store
+        .update_file(|secrets| {
+            secrets.clear();
+        })
+        .is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_secret_command_output_is_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    let counter = dir.path().join("runs");
+    let mut github = GitHubSource::default();
+    github.name = "GitHub".to_string();
+    let source = TaskSource::GitHub(github);
+    let store = SecretStore {
+        command: format!("sh -c echo>>{}&&echo${{IFS}}secret", counter.display()),
+        ..Default::default()
+    };
+    assert_eq!(Some("secret".to_string()), store.command_secret(&source));
+    assert_eq!(Some("secret".to_string()), store.command_secret(&source));
+    assert_eq!(
+        1,
+        std::fs::read_to_string(&counter).unwrap().lines().count()
+    );
+}
+
+#[test]
+fn test_delete_reports_keyring_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.json");
+    let store = SecretStore::default();
+    store.set_file(Some(path.clone()));
+    store.unlock("correct horse").unwrap();
+    store
+        .update_file(|secrets| {
+            secrets.insert(Uuid::from_u128(1), "token".to_string());
+        })
+        .unwrap();
+
+    // The secret is removed from the file, but the keyring entry is left
+    let result = store.delete_from_file(Uuid::from_u128(1), Err(anyhow!("No keyring")));
+    assert!(result.is_err());
+    assert_eq!(None, store.file_secret(Uuid::from_u128(1)));
+
+    // A locked file does not hide that the keyring entry has been deleted
+    let store = SecretStore::default();
+    store.set_file(Some(path));
+    assert!(store.is_locked());
+    assert!(store.delete_from_file(Uuid::from_u128(1), Ok(())).is_ok());
+}
@@ -0,0 +1,181 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::tasks::{inherited_due_dates, Task};
+
+/// A criterion to sort tasks by.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// Next due date first, parent tasks use the earliest due date of their
+    /// subtasks.
+    Due,
+    /// Highest priority first.
+    Priority,
+    /// In the order of the sources in the side panel.
+    Source,
+    /// Most recently updated first.
+    Updated,
+    /// Oldest first.
+    Created,
+    /// Highest score first, see [`ScoreWeights`].
+    Score,
+    Title,
+    Project,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 8] = [
+        SortKey::Due,
+        SortKey::Priority,
+        SortKey::Source,
+        SortKey::Updated,
+        SortKey::Created,
+        SortKey::Score,
+        SortKey::Title,
+        SortKey::Project,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortKey::Due => "Due date",
+            SortKey::Priority => "Priority",
+            SortKey::Source => "Source",
+            SortKey::Updated => "Recently updated",
+            SortKey::Created => "Creation date",
+            SortKey::Score => "Score",
+            SortKey::Title => "Title",
+            SortKey::Project => "Project",
+        }
+    }
+}
+
+/// Predefined combinations of sort keys.
+pub const PRESETS: [(&str, &[SortKey]); 5] = [
+    ("Due date", &[SortKey::Due, SortKey::Created]),
+    (
+        "Priority, then due date",
+        &[SortKey::Priority, SortKey::Due, SortKey::Created],
+    ),
+    (
+        "Source order",
+        &[SortKey::Source, SortKey::Due, SortKey::Created],
+    ),
+    ("Recently updated", &[SortKey::Updated, SortKey::Due]),
+    ("Score", &[SortKey::Score, SortKey::Due]),
+];
+
+/// How much each property of a task contributes to its score. Each property
+/// is mapped to a value between 0 and 1 before it is weighted.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ScoreWeights {
+    /// High priority is 1, low priority 0 and no priority 0.5.
+    pub priority: f32,
+    /// Overdue or due now is 1, decreasing with the number of days until the
+    /// task is due. Tasks without due date have 0.
+    pub due: f32,
+    /// Increases with the age of the task up to 1 after 30 days.
+    pub age: f32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            priority: 1.0,
+            due: 2.0,
+            age: 0.5,
+        }
+    }
+}
+
+/// The sort keys, in the order they are applied.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Sorting {
+    pub keys: Vec<SortKey>,
+    pub weights: ScoreWeights,
+}
+
+impl Default for Sorting {
+    fn default() -> Self {
+        Self {
+            keys: vec![SortKey::Due, SortKey::Created],
+            weights: ScoreWeights::default(),
+        }
+    }
+}
+
+/// Compare optional values, where missing values are sorted last.
+fn compare_optional<T: Ord>(a: &Option<T>, b: &Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+impl Sorting {
+    /// Calculate the score of a task with the given (possibly inherited) due
+    /// date.
+    pub fn score(&self, task: &Task, due: Option<DateTime<Utc>>, now: DateTime<Utc>) -> f32 {
+        let priority = task
+            .priority
+            .map(|p| f32::from(9 - p.clamp(1, 9)) / 8.0)
+            .unwrap_or(0.5);
+        let due = due
+            .map(|due| {
+                let days = (due - now).num_hours() as f32 / 24.0;
+                1.0 / (1.0 + days.max(0.0))
+            })
+            .unwrap_or(0.0);
+        let age = task
+            .created
+            .map(|created| ((now - created).num_days() as f32 / 30.0).clamp(0.0, 1.0))
+            .unwrap_or(0.0);
+        self.weights.priority * priority + self.weights.due * due + self.weights.age * age
+    }
+
+    /// Make the key the first one, keeping the order of the other keys.
+    pub fn sort_first_by(&mut self, key: SortKey) {
+        self.keys.retain(|k| *k != key);
+        self.keys.insert(0, key);
+    }
+
+    /// Sort the tasks by all keys. The sort is stable, so tasks that are
+    /// equal in all keys keep their order.
+    pub fn sort(&self, tasks: &mut [Task], source_order: &[Uuid], now: DateTime<Utc>) {
+        let due_dates = inherited_due_dates(tasks);
+        let due = |t: &Task| due_dates.get(&t.get_id()).copied().flatten();
+        let source_position = |t: &Task| source_order.iter().position(|s| *s == t.source_id);
+        let score = |t: &Task| self.score(t, due(t), now);
+
+        tasks.sort_by(|a, b| {
+            for key in &self.keys {
+                let ordering = match key {
+                    SortKey::Due => compare_optional(&due(a), &due(b), false),
+                    SortKey::Priority => compare_optional(&a.priority, &b.priority, false),
+                    SortKey::Source => {
+                        compare_optional(&source_position(a), &source_position(b), false)
+                    }
+                    SortKey::Updated => compare_optional(&a.updated, &b.updated, true),
+                    SortKey::Created => compare_optional(&a.created, &b.created, false),
+                    SortKey::Score => score(b).total_cmp(&score(a)),
+                    SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+                    SortKey::Project => a.project.to_lowercase().cmp(&b.project.to_lowercase()),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests;
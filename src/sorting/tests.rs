@@ -0,0 +1,225 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::tasks::Task;
+
+use super::{SortKey, Sorting};
+
+#[test]
+fn test_sort_by_due_and_creation_date() {
+    let mut tasks = vec![
+        Task {
+            title: "No due date, new".to_string(),
+            created: Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+        Task {
+            title: "Due later".to_string(),
+            due: Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+        Task {
+            title: "No due date, old".to_string(),
+            created: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+        Task {
+            title: "Due first".to_string(),
+            due: Some(Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+    ];
+    Sorting::default().sort(&mut tasks, &[], Utc::now());
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(
+        vec![
+            "Due first",
+            "Due later",
+            "No due date, old",
+            "No due date, new"
+        ],
+        titles
+    );
+}
+
+#[test]
+fn test_parent_inherits_earliest_subtask_due_date() {
+    let mut tasks = vec![
+        Task {
+            title: "Other".to_string(),
+            due: Some(Utc.with_ymd_and_hms(2024, 4, 10, 0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+        Task {
+            id: Some("parent".to_string()),
+            title: "Project".to_string(),
+            due: Some(Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+        Task {
+            title: "Subtask".to_string(),
+            due: Some(Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()),
+            parent: Some("parent".to_string()),
+            ..Default::default()
+        },
+    ];
+    Sorting::default().sort(&mut tasks, &[], Utc::now());
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(vec!["Project", "Subtask", "Other"], titles);
+}
+
+#[test]
+fn test_sort_by_priority_then_due_date() {
+    let due = |day| Some(Utc.with_ymd_and_hms(2024, 4, day, 0, 0, 0).unwrap());
+    let mut tasks = vec![
+        Task {
+            title: "Low".to_string(),
+            priority: Some(9),
+            due: due(1),
+            ..Default::default()
+        },
+        Task {
+            title: "No priority".to_string(),
+            due: due(1),
+            ..Default::default()
+        },
+        Task {
+            title: "High, due later".to_string(),
+            priority: Some(1),
+            due: due(20),
+            ..Default::default()
+        },
+        Task {
+            title: "High, due first".to_string(),
+            priority: Some(1),
+            due: due(10),
+            ..Default::default()
+        },
+    ];
+    let sorting = Sorting {
+        keys: vec![SortKey::Priority, SortKey::Due],
+        ..Default::default()
+    };
+    sorting.sort(&mut tasks, &[], Utc::now());
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(
+        vec!["High, due first", "High, due later", "Low", "No priority"],
+        titles
+    );
+}
+
+#[test]
+fn test_sort_by_source_and_update() {
+    let first_source = Uuid::from_u128(1);
+    let second_source = Uuid::from_u128(2);
+    let updated = |day| Some(Utc.with_ymd_and_hms(2024, 4, day, 0, 0, 0).unwrap());
+    let mut tasks = vec![
+        Task {
+            title: "Second source".to_string(),
+            source_id: second_source,
+            updated: updated(5),
+            ..Default::default()
+        },
+        Task {
+            title: "Unknown source".to_string(),
+            updated: updated(9),
+            ..Default::default()
+        },
+        Task {
+            title: "First source, old".to_string(),
+            source_id: first_source,
+            updated: updated(1),
+            ..Default::default()
+        },
+        Task {
+            title: "First source, recent".to_string(),
+            source_id: first_source,
+            updated: updated(3),
+            ..Default::default()
+        },
+    ];
+    let sorting = Sorting {
+        keys: vec![SortKey::Source, SortKey::Updated],
+        ..Default::default()
+    };
+    sorting.sort(&mut tasks, &[first_source, second_source], Utc::now());
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(
+        vec![
+            "First source, recent",
+            "First source, old",
+            "Second source",
+            "Unknown source"
+        ],
+        titles
+    );
+
+    // Without any key, the order is kept
+    Sorting {
+        keys: Vec::default(),
+        ..Default::default()
+    }
+    .sort(&mut tasks, &[], Utc::now());
+    assert_eq!("First source, recent", tasks[0].title);
+}
+
+#[test]
+fn test_score() {
+    let now = Utc.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap();
+    let sorting = Sorting::default();
+    let overdue = Task {
+        title: "Overdue".to_string(),
+        due: Some(now - TimeDelta::days(1)),
+        ..Default::default()
+    };
+    let due_next_week = Task {
+        title: "Due next week".to_string(),
+        due: Some(now + TimeDelta::days(7)),
+        ..Default::default()
+    };
+    let high_priority = Task {
+        title: "High priority".to_string(),
+        priority: Some(1),
+        ..Default::default()
+    };
+    // Due now (2) and medium priority (0.5)
+    assert_eq!(2.5, sorting.score(&overdue, overdue.due, now));
+    assert!(
+        sorting.score(&due_next_week, due_next_week.due, now)
+            < sorting.score(&overdue, overdue.due, now)
+    );
+    assert_eq!(1.0, sorting.score(&high_priority, None, now));
+
+    let mut tasks = vec![high_priority, due_next_week, overdue];
+    Sorting {
+        keys: vec![SortKey::Score],
+        ..Default::default()
+    }
+    .sort(&mut tasks, &[], now);
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(vec!["Overdue", "High priority", "Due next week"], titles);
+}
+
+#[test]
+fn test_sort_first_by_project() {
+    let mut sorting = Sorting::default();
+    sorting.sort_first_by(SortKey::Project);
+    assert_eq!(
+        vec![SortKey::Project, SortKey::Due, SortKey::Created],
+        sorting.keys
+    );
+    sorting.sort_first_by(SortKey::Created);
+    assert_eq!(
+        vec![SortKey::Created, SortKey::Project, SortKey::Due],
+        sorting.keys
+    );
+
+    let mut tasks = ["web", "Infra", "docs"].map(|project| Task {
+        project: project.to_string(),
+        ..Default::default()
+    });
+    sorting.sort_first_by(SortKey::Project);
+    sorting.sort(&mut tasks, &[], Utc::now());
+    let projects: Vec<_> = tasks.iter().map(|t| t.project.as_str()).collect();
+    assert_eq!(vec!["docs", "Infra", "web"], projects);
+}
@@ -1,21 +1,102 @@
 mod caldav;
-mod github;
+pub mod github;
 mod gitlab;
+pub mod oauth;
 mod openproject;
 
 pub use caldav::CalDavSource;
 pub use github::GitHubSource;
 pub use gitlab::GitLabSource;
-use keyring::Entry;
 pub use openproject::OpenProjectSource;
 
+use anyhow::Result;
+use log::warn;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use uuid::{Builder, Uuid};
+
+use crate::secrets::SecretStore;
+
+/// Upper limit of pages that are requested for a single query.
+const MAX_PAGES: usize = 10;
+
+/// Get the URL of the next page from a `Link` header, as used by GitHub and
+/// GitLab.
+fn next_page_url(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, rel) = part.split_once(';')?;
+        if rel.contains(r#"rel="next""#) {
+            Some(
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string(),
+            )
+        } else {
+            None
+        }
+    })
+}
+
+/// Namespace for the IDs of sources that have been created by older versions.
+const LEGACY_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6c1f_0b7e_5a3d_4e8f_9d2c_1b4a_7e6f_3c90);
+
+/// Derive the ID of a source from older versions from its kind and name, in
+/// the same way as a version 5 UUID. The ID is the same on every start, even
+/// if the settings have not been saved with the new ID yet.
+fn legacy_id(kind: SourceKind, name: &str) -> Uuid {
+    let mut hasher = Sha1::new();
+    hasher.update(LEGACY_ID_NAMESPACE.as_bytes());
+    hasher.update(format!("{}:{name}", kind.slug()).as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hasher.finalize()[..16]);
+    Builder::from_sha1_bytes(bytes).into_uuid()
+}
 
 pub const CALDAV_ICON: &str = egui_phosphor::regular::CALENDAR;
 pub const GITHUB_ICON: &str = egui_phosphor::regular::GITHUB_LOGO;
 pub const GITLAB_ICON: &str = egui_phosphor::regular::GITLAB_LOGO;
 pub const OPENPROJECT_ICON: &str = egui_phosphor::regular::INFINITY;
 
+/// The type of tracker a task or source belongs to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SourceKind {
+    CalDav,
+    GitHub,
+    GitLab,
+    OpenProject,
+}
+
+impl SourceKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SourceKind::CalDav => "CalDAV",
+            SourceKind::GitHub => "GitHub",
+            SourceKind::GitLab => "GitLab",
+            SourceKind::OpenProject => "OpenProject",
+        }
+    }
+
+    /// Short lowercase identifier, e.g. used as prefix for task IDs.
+    pub fn slug(&self) -> &'static str {
+        match self {
+            SourceKind::CalDav => "caldav",
+            SourceKind::GitHub => "github",
+            SourceKind::GitLab => "gitlab",
+            SourceKind::OpenProject => "openproject",
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            SourceKind::CalDav => CALDAV_ICON,
+            SourceKind::GitHub => GITHUB_ICON,
+            SourceKind::GitLab => GITLAB_ICON,
+            SourceKind::OpenProject => OPENPROJECT_ICON,
+        }
+    }
+}
+
 #[non_exhaustive]
 #[derive(Serialize, Deserialize, Clone)]
 pub enum TaskSource {
@@ -28,35 +109,87 @@ pub enum TaskSource {
 impl TaskSource {
     pub fn name(&self) -> &str {
         match self {
-            TaskSource::CalDav(s) => s.calendar_name.as_str(),
+            TaskSource::CalDav(s) => s.name.as_str(),
             TaskSource::GitHub(s) => s.name.as_str(),
             TaskSource::GitLab(s) => s.name.as_str(),
             TaskSource::OpenProject(s) => s.name.as_str(),
         }
     }
 
-    pub fn type_name(&self) -> &str {
+    pub fn id(&self) -> Uuid {
+        match self {
+            TaskSource::CalDav(s) => s.id,
+            TaskSource::GitHub(s) => s.id,
+            TaskSource::GitLab(s) => s.id,
+            TaskSource::OpenProject(s) => s.id,
+        }
+    }
+
+    pub fn kind(&self) -> SourceKind {
         match self {
-            TaskSource::CalDav(_) => "CalDAV",
-            TaskSource::GitHub(_) => "GitHub",
-            TaskSource::GitLab(_) => "GitLab",
-            TaskSource::OpenProject(_) => "OpenProject",
+            TaskSource::CalDav(_) => SourceKind::CalDav,
+            TaskSource::GitHub(_) => SourceKind::GitHub,
+            TaskSource::GitLab(_) => SourceKind::GitLab,
+            TaskSource::OpenProject(_) => SourceKind::OpenProject,
         }
     }
 
+    pub fn type_name(&self) -> &str {
+        self.kind().name()
+    }
+
     pub fn icon(&self) -> &str {
+        self.kind().icon()
+    }
+
+    /// Get the secret of this source for querying its tasks. OAuth tokens
+    /// that are about to expire are refreshed first and the new token is
+    /// stored.
+    pub fn secret(&self, secrets: &SecretStore) -> Result<Option<String>> {
+        match self {
+            TaskSource::GitLab(s) => {
+                // Get the secret while holding the lock, so a refresh that
+                // had to wait uses the token stored by the previous one
+                let _refresh_guard = s.lock_refresh();
+                let Some(secret) = secrets.get(self) else {
+                    return Ok(None);
+                };
+                match s.refresh_secret(&secret, !secrets.is_external(self))? {
+                    Some(new_secret) => {
+                        if let Err(e) = secrets.set(self, &new_secret) {
+                            warn!("Could not store refreshed token: {e}");
+                        }
+                        Ok(Some(new_secret))
+                    }
+                    None => Ok(Some(secret)),
+                }
+            }
+            _ => Ok(secrets.get(self)),
+        }
+    }
+
+    fn id_mut(&mut self) -> &mut Uuid {
         match self {
-            TaskSource::CalDav(_) => CALDAV_ICON,
-            TaskSource::GitHub(_) => GITHUB_ICON,
-            TaskSource::GitLab(_) => GITLAB_ICON,
-            TaskSource::OpenProject(_) => OPENPROJECT_ICON,
+            TaskSource::CalDav(s) => &mut s.id,
+            TaskSource::GitHub(s) => &mut s.id,
+            TaskSource::GitLab(s) => &mut s.id,
+            TaskSource::OpenProject(s) => &mut s.id,
         }
     }
 
-    /// Returns the secret (e.g. a password or a token) for this task source.
-    pub fn secret(&self) -> Option<String> {
-        let keyring_entry = Entry::new("task-picker", self.name()).ok()?;
-        let secret = keyring_entry.get_password().ok()?;
-        Some(secret)
+    /// Convert settings that have been stored by older versions, using what
+    /// has been found out about them during the last refresh.
+    pub fn migrate_legacy_settings(&mut self) {
+        if let TaskSource::CalDav(s) = self {
+            s.migrate_legacy_calendar();
+        }
+        // The name of CalDAV sources is only known after migrating the
+        // calendar
+        if self.id().is_nil() {
+            *self.id_mut() = legacy_id(self.kind(), self.name());
+        }
     }
 }
+
+#[cfg(test)]
+mod tests;
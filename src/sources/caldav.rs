@@ -1,37 +1,155 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
 
 use anyhow::{anyhow, Result};
-use chrono::{format::ParseErrorKind, prelude::*};
+use chrono::{format::ParseErrorKind, prelude::*, Days};
+use log::{debug, warn};
 
+use base64::prelude::*;
+use minicaldav::{
+    caldav::{self, CalendarRef, EventRef},
+    ical::Ical,
+    Credentials,
+};
+use recurrence::RecurrenceRule;
 use serde::{Deserialize, Serialize};
-use ureq::Agent;
+use sync::{parse_sync_response, sync_collection_request, CalendarSyncState};
+use ureq::{Agent, AgentBuilder};
 use url::Url;
+use uuid::Uuid;
+use xmltree::Element;
 
 use crate::tasks::Task;
 
-use super::CALDAV_ICON;
+use super::SourceKind;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct CalDavSource {
     #[serde(skip)]
     agent: ureq::Agent,
-    pub calendar_name: String,
+    /// Stable identifier of this source, which does not change when the
+    /// source is renamed. Sources of older versions have no ID, which is
+    /// read as nil ID and replaced by [`TaskSource::migrate_legacy_settings`].
+    #[serde(default)]
+    pub id: Uuid,
+    pub name: String,
     pub username: String,
     pub base_url: String,
+    /// URLs of the calendars of this account to include, with their name for
+    /// display. All calendars are included if this is empty.
+    pub calendars: BTreeMap<String, String>,
+    /// Older versions only supported a single calendar, which name was also
+    /// used as name for the source. It is moved to `name` and
+    /// `unresolved_calendar` by [`CalDavSource::migrate_legacy_calendar`].
+    #[serde(rename = "calendar_name", skip_serializing)]
+    legacy_calendar_name: Option<String>,
+    /// Name of a calendar from older versions, which is included until its
+    /// URL has been found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unresolved_calendar: Option<String>,
+    /// Calendars found for the unresolved calendar name during a refresh,
+    /// which is shared between all clones of this source.
+    #[serde(skip)]
+    resolved_calendars: Arc<Mutex<BTreeMap<String, String>>>,
+    /// If set, only include todos that are due within the given number of days.
+    pub due_within_days: Option<u32>,
+    /// State of the incremental synchronization for each calendar, which is
+    /// shared between all clones of this source.
+    #[serde(skip)]
+    sync_state: Arc<Mutex<HashMap<Url, CalendarSyncState>>>,
 }
 
 impl Default for CalDavSource {
     fn default() -> Self {
         Self {
             agent: Agent::new(),
-            calendar_name: String::default(),
+            id: Uuid::new_v4(),
+            name: "CalDAV".to_string(),
             username: String::default(),
             base_url: String::default(),
+            calendars: BTreeMap::default(),
+            legacy_calendar_name: None,
+            unresolved_calendar: None,
+            resolved_calendars: Arc::default(),
+            due_within_days: None,
+            sync_state: Arc::default(),
         }
     }
 }
 
+const CALENDARS_REQUEST: &str = r#"
+<d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+    <d:prop>
+        <d:displayname />
+        <d:resourcetype />
+        <c:supported-calendar-component-set />
+    </d:prop>
+</d:propfind>
+"#;
+
+/// Create a `calendar-query` REPORT body (RFC 4791, section 7.8) that
+/// requests all todos which match the given filters for their properties.
+fn todo_query(prop_filters: &str) -> String {
+    format!(
+        r#"
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+    <d:prop>
+        <d:getetag />
+        <c:calendar-data />
+    </d:prop>
+    <c:filter>
+        <c:comp-filter name="VCALENDAR">
+            <c:comp-filter name="VTODO">{prop_filters}
+            </c:comp-filter>
+        </c:comp-filter>
+    </c:filter>
+</c:calendar-query>
+"#
+    )
+}
+
+/// Filter for todos that have not been completed. It is not possible to
+/// filter by the `STATUS` property, because a text match implies that the
+/// property exists, so this relies on the `COMPLETED` timestamp that is set by
+/// clients when completing a todo.
+const NOT_COMPLETED_FILTER: &str = r#"
+                <c:prop-filter name="COMPLETED">
+                    <c:is-not-defined />
+                </c:prop-filter>"#;
+
+/// Filter for open todos that are due before the given time, if any. The
+/// time range is applied to the whole todo, which also matches todos without
+/// any date (RFC 4791, section 9.9). The time range must come before the
+/// property filters.
+fn open_todos_filter(due_before: Option<DateTime<Utc>>) -> String {
+    let mut filter = String::new();
+    if let Some(due_before) = due_before {
+        filter.push_str(&format!(
+            r#"
+                <c:time-range end="{}" />"#,
+            due_before.format("%Y%m%dT%H%M%SZ")
+        ));
+    }
+    filter.push_str(NOT_COMPLETED_FILTER);
+    filter
+}
+
+/// Todos without due date are always included when limiting the due date.
+fn is_due_before(due: Option<DateTime<Utc>>, due_before: Option<DateTime<Utc>>) -> bool {
+    match (due, due_before) {
+        (Some(due), Some(due_before)) => due < due_before,
+        _ => true,
+    }
+}
+
+/// Filter for recurring todos. Their series can be still open even if the
+/// first occurrences have been completed.
+const RECURRING_FILTER: &str = r#"
+                <c:prop-filter name="RRULE" />"#;
+
 const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
 const DATE_ONLY_FORMAT: &str = "%Y%m%d";
 const DATE_TIME_FORMAT_WITH_TZ: &str = "%Y%m%dT%H%M%S%#z";
@@ -72,74 +190,732 @@ fn parse_caldav_date(data: &str) -> Result<DateTime<Utc>> {
     }
 }
 
+fn has_status(err: &anyhow::Error, codes: &[u16]) -> bool {
+    matches!(
+        err.downcast_ref::<ureq::Error>(),
+        Some(ureq::Error::Status(code, _)) if codes.contains(code)
+    )
+}
+
+/// True if the server does not support `sync-collection`. Other errors, like
+/// a wrong password or an unavailable server, are only temporary.
+fn is_sync_unsupported_error(err: &anyhow::Error) -> bool {
+    has_status(err, &[403, 405, 501]) || err.downcast_ref::<xmltree::ParseError>().is_some()
+}
+
+/// Collect the properties of an iCalendar component by their name.
+///
+/// Multiple `CATEGORIES` properties are merged into a single comma-separated
+/// list, for all other properties the last one is used.
+fn component_properties(component: &Ical) -> HashMap<String, String> {
+    let mut result: HashMap<String, String> = HashMap::new();
+    for p in component.properties.iter() {
+        match result.get_mut(&p.name) {
+            Some(existing) if p.name == "CATEGORIES" => {
+                existing.push(',');
+                existing.push_str(&p.value);
+            }
+            _ => {
+                result.insert(p.name.clone(), p.value.clone());
+            }
+        }
+    }
+    result
+}
+
+/// Split a list of text values at the commas that are not escaped.
+fn split_text_list(value: &str) -> Vec<String> {
+    let mut result = Vec::default();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if c == ',' && !escaped {
+            result.push(unescape(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    result.push(unescape(&current));
+    result.retain(|v| !v.trim().is_empty());
+    result
+}
+
+/// Parse all dates of a property that can occur several times and can have
+/// multiple comma-separated values, like `EXDATE` or `RDATE`.
+fn multi_valued_dates(component: &Ical, name: &str) -> Result<Vec<DateTime<Utc>>> {
+    let mut result = Vec::default();
+    for p in component.properties.iter().filter(|p| p.name == name) {
+        for value in p.value.split(',').filter(|v| !v.is_empty()) {
+            result.push(parse_caldav_date(value)?);
+        }
+    }
+    Ok(result)
+}
+
+fn is_completed(props: &HashMap<String, String>) -> bool {
+    props
+        .get("STATUS")
+        .filter(|s| s.as_str() == "COMPLETED")
+        .is_some()
+        || props.contains_key("COMPLETED")
+}
+
+/// Information about the occurrence of a (possibly recurring) todo that is
+/// currently open.
+struct OpenOccurrence {
+    props: HashMap<String, String>,
+    start: Option<DateTime<Utc>>,
+    due: Option<DateTime<Utc>>,
+    recurrence_id: Option<DateTime<Utc>>,
+    parent: Option<String>,
+}
+
+/// Get the UID of the parent todo from the `RELATED-TO` property. If no
+/// relationship type is given, the parent relationship is the default.
+fn parent_uid(component: &Ical) -> Option<String> {
+    component
+        .properties
+        .iter()
+        .filter(|p| p.name == "RELATED-TO")
+        .find(|p| {
+            p.attributes
+                .get("RELTYPE")
+                .is_none_or(|t| t.eq_ignore_ascii_case("PARENT"))
+        })
+        .map(|p| p.value.clone())
+}
+
+/// Get the UID of the todo in the calendar object. All occurrences of a
+/// recurring todo share the same UID.
+fn todo_uid(calendar_object: &Ical) -> Option<String> {
+    calendar_object
+        .children
+        .iter()
+        .filter(|c| c.name == "VTODO")
+        .flat_map(|c| c.properties.iter())
+        .find(|p| p.name == "UID")
+        .map(|p| p.value.clone())
+}
+
+/// Subtasks refer to the UID of their parent, but the task ID of a recurring
+/// parent also contains its occurrence. Replace the UIDs with the task IDs.
+fn link_parents(tasks: &mut [Task], task_id_by_uid: &HashMap<String, String>) {
+    for task in tasks {
+        if let Some(task_id) = task.parent.as_ref().and_then(|p| task_id_by_uid.get(p)) {
+            task.parent = Some(task_id.clone());
+        }
+    }
+}
+
+/// Find the next open occurrence of the todo in the given calendar object.
+///
+/// Non-recurring todos are returned as they are, unless they are completed.
+/// For recurring todos, all occurrences that have been completed (either as
+/// overridden instance with a `RECURRENCE-ID` or by completing the whole
+/// series) or that have been excluded are skipped.
+fn next_open_occurrence(
+    calendar_object: &Ical,
+    now: DateTime<Utc>,
+) -> Result<Option<OpenOccurrence>> {
+    let components: Vec<&Ical> = calendar_object
+        .children
+        .iter()
+        .filter(|c| c.name == "VTODO")
+        .collect();
+    let Some(master) = components
+        .iter()
+        .find(|c| !c.properties.iter().any(|p| p.name == "RECURRENCE-ID"))
+        .or_else(|| components.first())
+    else {
+        return Ok(None);
+    };
+    let props = component_properties(master);
+    let parent = parent_uid(master);
+    let start = props
+        .get("DTSTART")
+        .map(|raw| parse_caldav_date(raw))
+        .transpose()?;
+    let due = props
+        .get("DUE")
+        .map(|raw| parse_caldav_date(raw))
+        .transpose()?;
+
+    let rrule = props
+        .get("RRULE")
+        .map(|raw| RecurrenceRule::parse(raw))
+        .transpose()?;
+    let rdates = multi_valued_dates(master, "RDATE")?;
+
+    // The series is defined by the start date, or the due date if no start
+    // date is given.
+    let series_start = start.or(due);
+    let (Some(series_start), true) = (series_start, rrule.is_some() || !rdates.is_empty()) else {
+        if is_completed(&props) {
+            return Ok(None);
+        }
+        return Ok(Some(OpenOccurrence {
+            props,
+            start,
+            due,
+            recurrence_id: None,
+            parent,
+        }));
+    };
+
+    let exdates = multi_valued_dates(master, "EXDATE")?;
+    let mut overrides = HashMap::new();
+    for c in components.iter().filter(|c| !std::ptr::eq(**c, *master)) {
+        let override_props = component_properties(c);
+        if let Some(recurrence_id) = override_props.get("RECURRENCE-ID") {
+            overrides.insert(parse_caldav_date(recurrence_id)?, override_props);
+        }
+    }
+
+    // When the whole series has been marked as completed, only the
+    // occurrences after the completion are still open.
+    let completed_until = if is_completed(&props) {
+        Some(
+            props
+                .get("COMPLETED")
+                .map(|raw| parse_caldav_date(raw))
+                .transpose()?
+                .unwrap_or(now),
+        )
+    } else {
+        None
+    };
+
+    let is_open = |occurrence: &DateTime<Utc>| {
+        let occurrence_due = match (start, due) {
+            (Some(start), Some(due)) => *occurrence + (due - start),
+            _ => *occurrence,
+        };
+        !exdates.contains(occurrence)
+            && completed_until.is_none_or(|completed| occurrence_due > completed)
+            && !overrides.get(occurrence).is_some_and(is_completed)
+    };
+
+    let from_rule = rrule
+        .as_ref()
+        .and_then(|rule| rule.occurrences(series_start).find(|o| is_open(o)));
+    let from_rdates = rdates.iter().filter(|o| is_open(o)).min().copied();
+    let next = match (from_rule, from_rdates) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    Ok(next.map(|occurrence| {
+        let offset = occurrence - series_start;
+        let props = overrides.remove(&occurrence).unwrap_or(props);
+        OpenOccurrence {
+            start: start.map(|s| s + offset),
+            due: due.map(|d| d + offset),
+            props,
+            recurrence_id: Some(occurrence),
+            parent,
+        }
+    }))
+}
+
+/// Get the first property with the given name from all successful `propstat`
+/// elements of a `response` element in a multistatus answer.
+fn find_prop<'a>(response: &'a Element, name: &str) -> Option<&'a Element> {
+    response
+        .children
+        .iter()
+        .filter_map(|c| c.as_element())
+        .filter(|c| c.name == "propstat")
+        .filter(|propstat| {
+            propstat
+                .get_child("status")
+                .and_then(|s| s.get_text())
+                .is_none_or(|s| s.contains(" 200 "))
+        })
+        .filter_map(|propstat| propstat.get_child("prop"))
+        .find_map(|prop| prop.get_child(name))
+}
+
+/// Extract the calendar objects from the multistatus answer of a
+/// `calendar-query` REPORT.
+fn parse_calendar_data(base_url: &Url, multistatus: &Element) -> Vec<EventRef> {
+    let mut result = Vec::default();
+    for response in multistatus.children.iter().filter_map(|c| c.as_element()) {
+        let href = response.get_child("href").and_then(|e| e.get_text());
+        let data = find_prop(response, "calendar-data").and_then(|e| e.get_text());
+        if let (Some(href), Some(data)) = (href, data) {
+            if let Ok(url) = base_url.join(&href) {
+                let etag = find_prop(response, "getetag")
+                    .and_then(|e| e.get_text())
+                    .map(|e| e.to_string());
+                result.push(EventRef {
+                    etag,
+                    url,
+                    data: data.to_string(),
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Extract all calendars that can contain todos from the multistatus answer
+/// to a [`CALENDARS_REQUEST`].
+fn parse_calendar_list(base_url: &Url, multistatus: &Element) -> Vec<CalendarRef> {
+    let mut result = Vec::default();
+    for response in multistatus.children.iter().filter_map(|c| c.as_element()) {
+        let is_calendar =
+            find_prop(response, "resourcetype").is_some_and(|e| e.get_child("calendar").is_some());
+        // Servers are allowed to omit the supported components, which means
+        // all components are supported
+        let supports_todos = find_prop(response, "supported-calendar-component-set")
+            .map(|set| {
+                set.children.iter().filter_map(|c| c.as_element()).any(|c| {
+                    c.name == "comp"
+                        && c.attributes.get("name").map(String::as_str) == Some("VTODO")
+                })
+            })
+            .unwrap_or(true);
+        if !is_calendar || !supports_todos {
+            continue;
+        }
+        let Some(href) = response.get_child("href").and_then(|e| e.get_text()) else {
+            continue;
+        };
+        let Ok(url) = base_url.join(&href) else {
+            continue;
+        };
+        let name = find_prop(response, "displayname")
+            .and_then(|e| e.get_text())
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| {
+                url.path_segments()
+                    .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+                    .unwrap_or_default()
+                    .to_string()
+            });
+        result.push(CalendarRef {
+            url,
+            name,
+            color: None,
+        });
+    }
+    result
+}
+
 impl CalDavSource {
-    pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
+    fn credentials<S>(&self, secret: Option<S>) -> Result<Credentials>
     where
         S: Into<String>,
     {
+        let secret = secret.ok_or_else(|| anyhow!("Missing password for CalDAV source"))?;
+        Ok(Credentials::Basic(self.username.clone(), secret.into()))
+    }
+
+    /// Find the URL of the collection that contains all calendars of the user.
+    ///
+    /// The configured URL is tried first. If it is not a CalDAV endpoint (e.g.
+    /// because only the server address was given) the well-known URL for
+    /// CalDAV from RFC 6764 is used instead.
+    fn calendar_home_set(&self, credentials: &Credentials) -> Result<Url> {
         let base_url = Url::parse(&self.base_url)?;
-        let secret = secret.ok_or_else(|| anyhow!("Missing passwort for CalDAV source"))?;
-        let credentials = minicaldav::Credentials::Basic(self.username.clone(), secret.into());
-        let calendars = minicaldav::get_calendars(self.agent.clone(), &credentials, &base_url)?;
-        let mut result = Vec::default();
-        for c in calendars {
-            if c.name().as_str() == self.calendar_name {
-                let (todos, _errors) = minicaldav::get_todos(self.agent.clone(), &credentials, &c)?;
-                for t in todos {
-                    let props: HashMap<String, String> = t
-                        .properties_todo()
-                        .into_iter()
-                        .map(|(k, v)| (k.to_string(), v.to_string()))
-                        .collect();
-                    let completed = props
-                        .get("STATUS")
-                        .filter(|s| s.as_str() == "COMPLETED")
-                        .is_some()
-                        || props.contains_key("COMPLETED");
-                    // Check start due date if this task is ready to be started on
-                    let start_due = props
-                        .get("DTSTART")
-                        .map(|raw| parse_caldav_date(raw))
-                        .transpose()?;
-                    let can_start = if let Some(start_due) = start_due {
-                        let start_due: DateTime<Local> = DateTime::from(start_due);
-                        Local::now().cmp(&start_due).is_ge()
-                    } else {
-                        true
-                    };
-                    if !completed && can_start {
-                        if let Some(title) = props.get("SUMMARY") {
-                            let title = unescape(title);
-                            let description: String = props
-                                .get("DESCRIPTION")
-                                .map(|s| unescape(s))
-                                .unwrap_or_default();
-                            let due = props
-                                .get("DUE")
-                                .map(|raw| parse_caldav_date(raw))
-                                .transpose()?;
-
-                            let created = props
-                                .get("CREATED")
-                                .map(|raw| parse_caldav_date(raw))
-                                .transpose()?;
-
-                            let task = Task {
-                                project: format!("{} {}", CALDAV_ICON, c.name()),
-                                title: title.clone(),
-                                description,
-                                due: due.map(DateTime::<Utc>::from),
-                                created: created.map(DateTime::<Utc>::from),
-                                id: props.get("UID").cloned(),
-                            };
-                            result.push(task);
-                        }
+        match caldav::get_home_set_url(self.agent.clone(), credentials, &base_url) {
+            Ok(url) => Ok(url),
+            Err(original_error) => {
+                let well_known = base_url.join("/.well-known/caldav")?;
+                // Redirects must be resolved manually, because other methods
+                // than GET would be changed to GET when following them.
+                let response = match AgentBuilder::new()
+                    .redirects(0)
+                    .build()
+                    .get(well_known.as_str())
+                    .call()
+                {
+                    Ok(response) => response,
+                    Err(_) => return Err(minicaldav::Error::from(original_error).into()),
+                };
+                let context_url = match response.header("Location") {
+                    Some(location) if (300..400).contains(&response.status()) => {
+                        well_known.join(location)?
+                    }
+                    _ => well_known,
+                };
+                let url = caldav::get_home_set_url(self.agent.clone(), credentials, &context_url)
+                    .map_err(minicaldav::Error::from)?;
+                Ok(url)
+            }
+        }
+    }
+
+    fn get_calendars(&self, credentials: &Credentials) -> Result<Vec<CalendarRef>> {
+        let home_set = self.calendar_home_set(credentials)?;
+        let (_, multistatus) = caldav::propfind_get(
+            self.agent.clone(),
+            credentials,
+            &home_set,
+            CALENDARS_REQUEST,
+            &[],
+            "1",
+        )
+        .map_err(minicaldav::Error::from)?;
+        Ok(parse_calendar_list(&home_set, &multistatus))
+    }
+
+    /// Get the URLs and names of all calendars of this account that can
+    /// contain todos.
+    pub fn discover_calendars<S>(&self, secret: Option<S>) -> Result<Vec<(String, String)>>
+    where
+        S: Into<String>,
+    {
+        let credentials = self.credentials(secret)?;
+        let calendars = self.get_calendars(&credentials)?;
+        Ok(calendars
+            .into_iter()
+            .map(|c| (c.url.to_string(), c.name))
+            .collect())
+    }
+
+    /// Sources from older versions have no list of calendars, but use the
+    /// calendar name as source name. The calendar is included by its name
+    /// until its URL has been found by a refresh, so the source can be
+    /// renamed.
+    pub fn migrate_legacy_calendar(&mut self) {
+        if let Some(calendar_name) = self.legacy_calendar_name.take() {
+            self.name = calendar_name.clone();
+            self.unresolved_calendar = Some(calendar_name);
+        }
+        let resolved =
+            std::mem::take(&mut *self.resolved_calendars.lock().expect("Lock poisoning"));
+        if !resolved.is_empty() {
+            self.calendars.extend(resolved);
+            self.unresolved_calendar = None;
+        }
+    }
+
+    /// Select the calendars with the given name, which has been used to
+    /// identify calendars by older versions.
+    pub fn resolve_calendar_name(&mut self, calendars: &[(String, String)]) {
+        let Some(calendar_name) = &self.unresolved_calendar else {
+            return;
+        };
+        let found: Vec<_> = calendars
+            .iter()
+            .filter(|(_, name)| name == calendar_name)
+            .cloned()
+            .collect();
+        if !found.is_empty() {
+            self.calendars.extend(found);
+            self.unresolved_calendar = None;
+        }
+    }
+
+    fn is_included(&self, calendar: &CalendarRef) -> bool {
+        if self.unresolved_calendar.as_ref() == Some(&calendar.name) {
+            return true;
+        }
+        (self.calendars.is_empty() && self.unresolved_calendar.is_none())
+            || self.calendars.contains_key(calendar.url.as_str())
+    }
+
+    fn report(
+        &self,
+        credentials: &Credentials,
+        url: &Url,
+        depth: &str,
+        body: &str,
+    ) -> Result<Element> {
+        let authorization = match credentials {
+            Credentials::Basic(username, password) => format!(
+                "Basic {}",
+                BASE64_STANDARD.encode(format!("{username}:{password}"))
+            ),
+            Credentials::Bearer(token) => format!("Bearer {token}"),
+        };
+        let response = self
+            .agent
+            .request("REPORT", url.as_str())
+            .set("Authorization", &authorization)
+            .set("Depth", depth)
+            .set("Content-Type", "application/xml")
+            .send_string(body)?;
+        let body = response.into_string()?;
+        Ok(Element::parse(body.as_bytes())?)
+    }
+
+    /// Only download the todos that are still open, by letting the server
+    /// filter them.
+    fn query_open_todos(
+        &self,
+        credentials: &Credentials,
+        calendar: &CalendarRef,
+    ) -> Result<Vec<EventRef>> {
+        let filter = open_todos_filter(self.due_before(Utc::now()));
+        let open = self.report(credentials, &calendar.url, "1", &todo_query(&filter))?;
+        let recurring = self.report(
+            credentials,
+            &calendar.url,
+            "1",
+            &todo_query(RECURRING_FILTER),
+        )?;
+
+        let mut result = parse_calendar_data(&calendar.url, &open);
+        for todo in parse_calendar_data(&calendar.url, &recurring) {
+            if !result.iter().any(|existing| existing.url == todo.url) {
+                result.push(todo);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Download only the calendar objects that changed since the last
+    /// refresh, using a `sync-collection` REPORT (RFC 6578).
+    ///
+    /// Returns `None` if the server does not support the synchronization.
+    /// Other errors are returned, so the synchronization is tried again on
+    /// the next refresh.
+    fn synchronize(
+        &self,
+        credentials: &Credentials,
+        calendar: &CalendarRef,
+        state: &mut CalendarSyncState,
+    ) -> Result<Option<Vec<EventRef>>> {
+        loop {
+            let request = sync_collection_request(state.token.as_deref());
+            match self.report(credentials, &calendar.url, "0", &request) {
+                Ok(multistatus) => {
+                    if let Some(changes) = parse_sync_response(&calendar.url, &multistatus) {
+                        state.apply(changes);
+                        return Ok(Some(state.objects()));
                     }
                 }
+                Err(e) if state.token.is_some() && has_status(&e, &[403, 409, 412]) => {
+                    // The token might have expired, try again with a full
+                    // synchronization.
+                    debug!(
+                        "Synchronizing calendar {} failed, starting from scratch. {e}",
+                        calendar.url
+                    );
+                    state.reset();
+                    continue;
+                }
+                Err(e) if is_sync_unsupported_error(&e) => {
+                    debug!(
+                        "Synchronizing calendar {} is not supported. {e}",
+                        calendar.url
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+            state.reset();
+            state.unsupported = true;
+            return Ok(None);
+        }
+    }
+
+    fn get_todos(
+        &self,
+        credentials: &Credentials,
+        calendar: &CalendarRef,
+    ) -> Result<Vec<EventRef>> {
+        // Don't hold the lock while waiting for the server
+        let mut state = self
+            .sync_state
+            .lock()
+            .expect("Lock poisoning")
+            .remove(&calendar.url)
+            .unwrap_or_default();
+        let synchronized = if state.unsupported {
+            Ok(None)
+        } else {
+            self.synchronize(credentials, calendar, &mut state)
+        };
+        self.sync_state
+            .lock()
+            .expect("Lock poisoning")
+            .insert(calendar.url.clone(), state);
+        if let Some(todos) = synchronized? {
+            return Ok(todos);
+        }
+
+        match self.query_open_todos(credentials, calendar) {
+            Ok(todos) => Ok(todos),
+            Err(e) => {
+                // Not all servers support filtering todos, so fall back to
+                // downloading all of them.
+                debug!(
+                    "Filtering todos for calendar {} failed, downloading all todos instead. {e}",
+                    calendar.url
+                );
+                let todos =
+                    caldav::get_todos(self.agent.clone(), credentials, &calendar.url, calendar)
+                        .map_err(minicaldav::Error::from)?;
+                Ok(todos)
+            }
+        }
+    }
+
+    fn due_before(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.due_within_days
+            .and_then(|days| now.checked_add_days(Days::new(days.into())))
+    }
+
+    /// Create a task for the next open occurrence of the todo in the calendar
+    /// object, if there is any and if it can already be started at `now`.
+    fn create_task(
+        &self,
+        calendar_name: &str,
+        calendar_object: &Ical,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Task>> {
+        let Some(occurrence) = next_open_occurrence(calendar_object, now)? else {
+            return Ok(None);
+        };
+        let props = occurrence.props;
+
+        // Check start due date if this task is ready to be started on
+        let can_start = if let Some(start_due) = occurrence.start {
+            now.cmp(&start_due).is_ge()
+        } else {
+            true
+        };
+        if !can_start {
+            return Ok(None);
+        }
+        if let Some(title) = props.get("SUMMARY") {
+            let title = unescape(title);
+            let description: String = props
+                .get("DESCRIPTION")
+                .map(|s| unescape(s))
+                .unwrap_or_default();
+
+            let created = props
+                .get("CREATED")
+                .map(|raw| parse_caldav_date(raw))
+                .transpose()?;
+            let updated = props
+                .get("LAST-MODIFIED")
+                .map(|raw| parse_caldav_date(raw))
+                .transpose()?;
+
+            // Each occurrence of a recurring task is a task on its own
+            let id = match (props.get("UID"), occurrence.recurrence_id) {
+                (Some(uid), Some(recurrence_id)) => {
+                    Some(format!("{uid}#{}", recurrence_id.format(DATE_TIME_FORMAT)))
+                }
+                (uid, _) => uid.cloned(),
+            };
+
+            // Priority 0 means the priority is undefined
+            let priority = props
+                .get("PRIORITY")
+                .and_then(|p| p.parse::<u8>().ok())
+                .filter(|p| *p > 0);
+            let percent_complete = props
+                .get("PERCENT-COMPLETE")
+                .and_then(|p| p.parse::<u8>().ok());
+            let labels = props
+                .get("CATEGORIES")
+                .map(|c| split_text_list(c))
+                .unwrap_or_default();
+            let status = props
+                .get("STATUS")
+                .filter(|s| s.as_str() == "IN-PROCESS")
+                .map(|_| "In progress".to_string());
+
+            let task = Task {
+                project: calendar_name.to_string(),
+                title,
+                description,
+                due: occurrence.due,
+                created,
+                id,
+                source_id: self.id,
+                source_name: self.name.clone(),
+                source_kind: Some(SourceKind::CalDav),
+                start: occurrence.start,
+                updated,
+                priority,
+                percent_complete,
+                labels,
+                location: props.get("LOCATION").map(|l| unescape(l)),
+                url: props.get("URL").cloned(),
+                status,
+                parent: occurrence.parent,
+                ..Default::default()
+            };
+            Ok(Some(task))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
+    where
+        S: Into<String>,
+    {
+        let credentials = self.credentials(secret)?;
+        let calendars = self.get_calendars(&credentials)?;
+        let now = Utc::now();
+        let due_before = self.due_before(now);
+        let mut result = Vec::default();
+        let mut task_id_by_uid = HashMap::new();
+        for c in calendars.iter().filter(|c| self.is_included(c)) {
+            if self.unresolved_calendar.as_ref() == Some(&c.name) {
+                // Remember the URL, so the calendar stays included when it is
+                // renamed
+                self.resolved_calendars
+                    .lock()
+                    .expect("Lock poisoning")
+                    .insert(c.url.to_string(), c.name.clone());
+            }
+            let todos = self.get_todos(&credentials, c)?;
+            for task in self.create_calendar_tasks(&c.name, &todos, now, &mut task_id_by_uid) {
+                // Not all todos might have been filtered by the server, and
+                // the time range also matches todos that start earlier
+                if is_due_before(task.due, due_before) {
+                    result.push(task);
+                }
             }
         }
+        link_parents(&mut result, &task_id_by_uid);
         Ok(result)
     }
+
+    /// Create the tasks for the todos of a calendar and remember their task
+    /// IDs by UID. Todos that can't be parsed are skipped, so they don't hide
+    /// all other tasks of the source.
+    fn create_calendar_tasks(
+        &self,
+        calendar_name: &str,
+        todos: &[EventRef],
+        now: DateTime<Utc>,
+        task_id_by_uid: &mut HashMap<String, String>,
+    ) -> Vec<Task> {
+        let mut result = Vec::default();
+        for t in todos {
+            let calendar_object = match minicaldav::parse_ical(&t.data) {
+                Ok(calendar_object) => calendar_object,
+                Err(e) => {
+                    warn!("Ignoring todo {} that could not be parsed: {e}", t.url);
+                    continue;
+                }
+            };
+            let task = match self.create_task(calendar_name, &calendar_object, now) {
+                Ok(Some(task)) => task,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Ignoring todo {} that could not be read: {e}", t.url);
+                    continue;
+                }
+            };
+            if let (Some(uid), Some(task_id)) = (todo_uid(&calendar_object), &task.id) {
+                task_id_by_uid.insert(uid, task_id.clone());
+            }
+            result.push(task);
+        }
+        result
+    }
 }
 
 /// Unescape some known escaped characters in CalDAV.
@@ -190,5 +966,8 @@ fn unescape(val: &str) -> String {
     unescaped
 }
 
+mod recurrence;
+mod sync;
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{prelude::*, Days, Months};
+use log::warn;
+
+use super::parse_caldav_date;
+
+/// Upper limit of recurrence periods that are inspected before giving up. This
+/// protects against rules that never produce a matching occurrence.
+const MAX_PERIODS: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The subset of an iCalendar `RRULE` (RFC 5545, section 3.3.10) that is
+/// needed to compute the occurrences of typical recurring todos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    freq: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<DateTime<Utc>>,
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+fn parse_weekday(value: &str) -> Result<Weekday> {
+    match value {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(anyhow!("Invalid weekday '{value}' in RRULE")),
+    }
+}
+
+impl RecurrenceRule {
+    pub fn parse(rule: &str) -> Result<Self> {
+        let mut freq = None;
+        let mut result = RecurrenceRule {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::default(),
+            by_month_day: Vec::default(),
+            by_month: Vec::default(),
+        };
+        for part in rule.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .with_context(|| format!("Invalid RRULE part '{part}'"))?;
+            match key {
+                "FREQ" => {
+                    freq = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => bail!("Unsupported RRULE frequency '{value}'"),
+                    })
+                }
+                "INTERVAL" => result.interval = value.parse::<u32>()?.max(1),
+                "COUNT" => result.count = Some(value.parse()?),
+                "UNTIL" => result.until = Some(parse_caldav_date(value)?),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        if !day.is_ascii() {
+                            bail!("Invalid BYDAY value '{day}'");
+                        }
+                        let (ordinal, weekday) = day.split_at(day.len().saturating_sub(2));
+                        let ordinal = if ordinal.is_empty() {
+                            None
+                        } else {
+                            Some(ordinal.trim_start_matches('+').parse()?)
+                        };
+                        result.by_day.push((ordinal, parse_weekday(weekday)?));
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        result.by_month_day.push(day.parse()?);
+                    }
+                }
+                "BYMONTH" => {
+                    for month in value.split(',') {
+                        result.by_month.push(month.parse()?);
+                    }
+                }
+                "WKST" => {}
+                _ => warn!("Ignoring unsupported RRULE part '{part}'"),
+            }
+        }
+        result.freq = freq.context("RRULE is missing the FREQ part")?;
+        Ok(result)
+    }
+
+    /// Returns an iterator over all occurrences of this rule, in ascending
+    /// order and starting with `dtstart` itself.
+    pub fn occurrences(&self, dtstart: DateTime<Utc>) -> Occurrences<'_> {
+        Occurrences {
+            rule: self,
+            start: dtstart.with_timezone(&Local).naive_local(),
+            period: 0,
+            emitted: 0,
+            buffer: Vec::default(),
+        }
+    }
+
+    /// All candidate dates for the given period, not necessarily sorted.
+    fn dates_in_period(&self, start: NaiveDate, period: u32) -> Vec<NaiveDate> {
+        let steps = period.saturating_mul(self.interval);
+        match self.freq {
+            Frequency::Daily => start
+                .checked_add_days(Days::new(steps.into()))
+                .filter(|d| self.matches_filters(*d))
+                .into_iter()
+                .collect(),
+            Frequency::Weekly => {
+                if self.by_day.is_empty() {
+                    start
+                        .checked_add_days(Days::new(u64::from(steps) * 7))
+                        .filter(|d| self.matches_filters(*d))
+                        .into_iter()
+                        .collect()
+                } else {
+                    let week_start = start.week(Weekday::Mon).first_day();
+                    let Some(week_start) =
+                        week_start.checked_add_days(Days::new(u64::from(steps) * 7))
+                    else {
+                        return Vec::default();
+                    };
+                    self.by_day
+                        .iter()
+                        .filter_map(|(_, weekday)| {
+                            week_start
+                                .checked_add_days(Days::new(weekday.num_days_from_monday().into()))
+                        })
+                        .filter(|d| self.by_month.is_empty() || self.by_month.contains(&d.month()))
+                        .collect()
+                }
+            }
+            Frequency::Monthly => {
+                let Some(month) = start
+                    .with_day(1)
+                    .and_then(|d| d.checked_add_months(Months::new(steps)))
+                else {
+                    return Vec::default();
+                };
+                if !self.by_month.is_empty() && !self.by_month.contains(&month.month()) {
+                    return Vec::default();
+                }
+                self.dates_in_month(month, start.day())
+            }
+            Frequency::Yearly => {
+                let Some(year) = start
+                    .with_day(1)
+                    .and_then(|d| d.with_month(1))
+                    .and_then(|d| d.checked_add_months(Months::new(steps.saturating_mul(12))))
+                else {
+                    return Vec::default();
+                };
+                let months = if self.by_month.is_empty() {
+                    vec![start.month()]
+                } else {
+                    self.by_month.clone()
+                };
+                months
+                    .into_iter()
+                    .filter_map(|m| year.with_month(m))
+                    .flat_map(|month| {
+                        if self.by_day.is_empty() && self.by_month_day.is_empty() {
+                            month.with_day(start.day()).into_iter().collect()
+                        } else {
+                            self.dates_in_month(month, start.day())
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Expand the `BYMONTHDAY` and `BYDAY` parts for the month starting at
+    /// `month`. Without these parts, the day of the month of the start date is
+    /// used.
+    fn dates_in_month(&self, month: NaiveDate, default_day: u32) -> Vec<NaiveDate> {
+        let days_in_month = month
+            .checked_add_months(Months::new(1))
+            .and_then(|next| next.pred_opt())
+            .map(|last| last.day())
+            .unwrap_or(28);
+        if !self.by_month_day.is_empty() {
+            self.by_month_day
+                .iter()
+                .filter_map(|day| {
+                    let day = if *day < 0 {
+                        i64::from(days_in_month) + i64::from(*day) + 1
+                    } else {
+                        i64::from(*day)
+                    };
+                    u32::try_from(day).ok().and_then(|d| month.with_day(d))
+                })
+                .filter(|d| {
+                    self.by_day.is_empty() || self.by_day.iter().any(|(_, wd)| d.weekday() == *wd)
+                })
+                .collect()
+        } else if !self.by_day.is_empty() {
+            let mut result = Vec::default();
+            for (ordinal, weekday) in &self.by_day {
+                let matching: Vec<NaiveDate> = (1..=days_in_month)
+                    .filter_map(|d| month.with_day(d))
+                    .filter(|d| d.weekday() == *weekday)
+                    .collect();
+                match ordinal {
+                    None => result.extend(matching),
+                    Some(n) if *n > 0 => {
+                        result.extend(matching.get((*n - 1) as usize).copied());
+                    }
+                    Some(n) => {
+                        let from_end = matching.len() as i32 + *n;
+                        if from_end >= 0 {
+                            result.extend(matching.get(from_end as usize).copied());
+                        }
+                    }
+                }
+            }
+            result
+        } else {
+            month.with_day(default_day).into_iter().collect()
+        }
+    }
+
+    fn matches_filters(&self, date: NaiveDate) -> bool {
+        (self.by_month.is_empty() || self.by_month.contains(&date.month()))
+            && (self.by_day.is_empty() || self.by_day.iter().any(|(_, wd)| date.weekday() == *wd))
+            && (self.by_month_day.is_empty() || self.by_month_day.contains(&(date.day() as i32)))
+    }
+}
+
+/// Iterator over the occurrences of a [`RecurrenceRule`].
+pub struct Occurrences<'a> {
+    rule: &'a RecurrenceRule,
+    start: NaiveDateTime,
+    period: u32,
+    emitted: u32,
+    buffer: Vec<DateTime<Utc>>,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(count) = self.rule.count {
+            if self.emitted >= count {
+                return None;
+            }
+        }
+        while self.buffer.is_empty() {
+            if self.period > MAX_PERIODS {
+                return None;
+            }
+            let mut dates = if self.period == 0 {
+                // The start is always the first occurrence, even if it does
+                // not match the rule.
+                let mut dates = self.rule.dates_in_period(self.start.date(), 0);
+                dates.push(self.start.date());
+                dates
+            } else {
+                self.rule.dates_in_period(self.start.date(), self.period)
+            };
+            self.period += 1;
+            dates.sort_unstable();
+            dates.dedup();
+            // Store in reverse order, so we can pop the earliest one
+            self.buffer = dates
+                .into_iter()
+                .filter(|d| *d >= self.start.date())
+                .filter_map(|d| {
+                    d.and_time(self.start.time())
+                        .and_local_timezone(Local)
+                        .earliest()
+                })
+                .map(|d| d.with_timezone(&Utc))
+                .rev()
+                .collect();
+        }
+        let next = self.buffer.pop()?;
+        if self.rule.until.is_some_and(|until| next > until) {
+            self.buffer.clear();
+            self.period = MAX_PERIODS + 1;
+            return None;
+        }
+        self.emitted += 1;
+        Some(next)
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use minicaldav::caldav::EventRef;
+use url::Url;
+use xmltree::Element;
+
+use super::find_prop;
+
+/// Create a `sync-collection` REPORT body (RFC 6578, section 3.2). Without a
+/// token, all calendar objects of the collection are returned.
+pub fn sync_collection_request(token: Option<&str>) -> String {
+    format!(
+        r#"
+<d:sync-collection xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+    <d:sync-token>{}</d:sync-token>
+    <d:sync-level>1</d:sync-level>
+    <d:prop>
+        <d:getetag />
+        <c:calendar-data />
+    </d:prop>
+</d:sync-collection>
+"#,
+        token.unwrap_or_default()
+    )
+}
+
+/// Changes to a collection since the last synchronization.
+#[derive(Debug, Default)]
+pub struct SyncChanges {
+    pub token: String,
+    pub changed: Vec<EventRef>,
+    pub removed: Vec<Url>,
+}
+
+/// Parse the answer to a `sync-collection` REPORT. Returns `None` if the
+/// answer is not complete, e.g. because the server does not include the
+/// calendar data or a new token.
+pub fn parse_sync_response(base_url: &Url, multistatus: &Element) -> Option<SyncChanges> {
+    let mut changes = SyncChanges {
+        token: multistatus.get_child("sync-token")?.get_text()?.to_string(),
+        ..Default::default()
+    };
+    for response in multistatus
+        .children
+        .iter()
+        .filter_map(|c| c.as_element())
+        .filter(|c| c.name == "response")
+    {
+        let url = base_url
+            .join(&response.get_child("href")?.get_text()?)
+            .ok()?;
+        let removed = response
+            .get_child("status")
+            .and_then(|s| s.get_text())
+            .is_some_and(|s| s.contains(" 404 "));
+        if removed {
+            changes.removed.push(url);
+        } else if url.path() != base_url.path() {
+            // The collection itself might be part of the response
+            let data = find_prop(response, "calendar-data")?.get_text()?;
+            let etag = find_prop(response, "getetag")
+                .and_then(|e| e.get_text())
+                .map(|e| e.to_string());
+            changes.changed.push(EventRef {
+                etag,
+                url,
+                data: data.to_string(),
+            });
+        }
+    }
+    Some(changes)
+}
+
+/// Local copy of all calendar objects of a collection and the token of the
+/// last synchronization.
+#[derive(Default)]
+pub struct CalendarSyncState {
+    pub token: Option<String>,
+    /// Calendar objects by their URL
+    objects: HashMap<Url, EventRef>,
+    /// Set when the server does not support synchronizing this collection.
+    pub unsupported: bool,
+}
+
+impl CalendarSyncState {
+    pub fn apply(&mut self, changes: SyncChanges) {
+        if self.token.is_none() {
+            // Initial synchronization, which includes all objects
+            self.objects.clear();
+        }
+        for url in changes.removed {
+            self.objects.remove(&url);
+        }
+        for object in changes.changed {
+            self.objects.insert(object.url.clone(), object);
+        }
+        self.token = Some(changes.token);
+    }
+
+    /// Forget the token and all objects, so the next synchronization
+    /// starts from scratch.
+    pub fn reset(&mut self) {
+        self.token = None;
+        self.objects.clear();
+    }
+
+    pub fn objects(&self) -> Vec<EventRef> {
+        self.objects.values().cloned().collect()
+    }
+}
@@ -1,6 +1,21 @@
-use chrono::Datelike;
+use std::collections::{BTreeMap, HashMap};
 
-use super::parse_caldav_date;
+use chrono::{Datelike, TimeZone, Utc};
+use minicaldav::{
+    caldav::{CalendarRef, EventRef},
+    ical::Ical,
+    Credentials,
+};
+use url::Url;
+use xmltree::Element;
+
+use super::{
+    is_due_before, link_parents, open_todos_filter, parse_caldav_date, parse_calendar_data,
+    parse_calendar_list,
+    recurrence::RecurrenceRule,
+    sync::{parse_sync_response, CalendarSyncState},
+    todo_uid, CalDavSource,
+};
 
 #[test]
 fn test_date_without_time() {
@@ -8,3 +23,625 @@ fn test_date_without_time() {
     assert_eq!(t.date_naive().year(), 2024);
     assert_eq!(t.date_naive().month0(), 3);
 }
+
+#[test]
+fn test_invalid_byday_is_an_error() {
+    assert!(RecurrenceRule::parse("FREQ=WEEKLY;BYDAY=x€").is_err());
+    assert!(RecurrenceRule::parse("FREQ=WEEKLY;BYDAY=€").is_err());
+    assert!(RecurrenceRule::parse("FREQ=MONTHLY;BYDAY=-1FR").is_ok());
+}
+
+fn parse_todo(ical: &str) -> Ical {
+    // Use CRLF line endings, as they are used by CalDAV servers
+    minicaldav::parse_ical(&ical.replace('\n', "\r\n")).unwrap()
+}
+
+#[test]
+fn test_weekly_recurring_todo_skips_completed_occurrences() {
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:weekly-report
+SUMMARY:Weekly report
+DTSTART:20240101T090000Z
+DUE:20240101T170000Z
+RRULE:FREQ=WEEKLY;BYDAY=MO
+END:VTODO
+BEGIN:VTODO
+UID:weekly-report
+RECURRENCE-ID:20240101T090000Z
+SUMMARY:Weekly report
+STATUS:COMPLETED
+END:VTODO
+BEGIN:VTODO
+UID:weekly-report
+RECURRENCE-ID:20240108T090000Z
+SUMMARY:Weekly report
+COMPLETED:20240108T120000Z
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+    let task = CalDavSource::default()
+        .create_task("Work", &todo, now)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2024, 1, 15, 17, 0, 0).unwrap()),
+        task.due
+    );
+    assert_eq!(Some("weekly-report#20240115T090000"), task.id.as_deref());
+}
+
+#[test]
+fn test_recurring_todo_respects_exdate_and_count() {
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:invoice
+SUMMARY:Monthly invoicing
+DUE:20240131T120000Z
+RRULE:FREQ=MONTHLY;BYMONTHDAY=-1;COUNT=3
+EXDATE:20240131T120000Z
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+    let task = CalDavSource::default()
+        .create_task("Work", &todo, now)
+        .unwrap()
+        .unwrap();
+    let due = task.due.unwrap();
+    assert_eq!(2, due.month());
+    assert_eq!(29, due.day());
+
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:invoice
+SUMMARY:Monthly invoicing
+DUE:20240131T120000Z
+RRULE:FREQ=MONTHLY;COUNT=1
+STATUS:COMPLETED
+COMPLETED:20240131T130000Z
+END:VTODO
+END:VCALENDAR
+",
+    );
+    assert!(CalDavSource::default()
+        .create_task("Work", &todo, now)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_completed_series_continues_after_completion() {
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:chore
+SUMMARY:Water plants
+DUE:20240102T080000Z
+RRULE:FREQ=DAILY;INTERVAL=2
+STATUS:COMPLETED
+COMPLETED:20240105T100000Z
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let now = Utc.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap();
+    let task = CalDavSource::default()
+        .create_task("Home", &todo, now)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2024, 1, 6, 8, 0, 0).unwrap()),
+        task.due
+    );
+}
+
+#[test]
+fn test_parse_calendar_list_only_includes_todo_calendars() {
+    let response = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/remote.php/dav/calendars/user/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/calendars/user/personal/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:displayname>Personal</d:displayname>
+        <d:resourcetype><d:collection/><cal:calendar/></d:resourcetype>
+        <cal:supported-calendar-component-set>
+          <cal:comp name="VEVENT"/>
+          <cal:comp name="VTODO"/>
+        </cal:supported-calendar-component-set>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/calendars/user/birthdays/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:displayname>Birthdays</d:displayname>
+        <d:resourcetype><d:collection/><cal:calendar/></d:resourcetype>
+        <cal:supported-calendar-component-set>
+          <cal:comp name="VEVENT"/>
+        </cal:supported-calendar-component-set>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/calendars/user/chores/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype><d:collection/><cal:calendar/></d:resourcetype>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop>
+        <d:displayname/>
+        <cal:supported-calendar-component-set/>
+      </d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+    let multistatus = Element::parse(response.as_bytes()).unwrap();
+    let base_url = Url::parse("https://example.com/remote.php/dav/calendars/user/").unwrap();
+    let calendars = parse_calendar_list(&base_url, &multistatus);
+
+    assert_eq!(2, calendars.len());
+    assert_eq!("Personal", calendars[0].name);
+    assert_eq!(
+        "https://example.com/remote.php/dav/calendars/user/personal/",
+        calendars[0].url.as_str()
+    );
+    assert_eq!("chores", calendars[1].name);
+}
+
+#[test]
+fn test_parse_calendar_data_from_calendar_query() {
+    let response = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/dav/calendars/user/personal/todo-1.ics</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>"1234"</d:getetag>
+        <cal:calendar-data>BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:todo-1
+SUMMARY:Open todo
+END:VTODO
+END:VCALENDAR
+</cal:calendar-data>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/dav/calendars/user/personal/deleted.ics</d:href>
+    <d:status>HTTP/1.1 404 Not Found</d:status>
+  </d:response>
+</d:multistatus>"#;
+    let multistatus = Element::parse(response.as_bytes()).unwrap();
+    let base_url = Url::parse("https://example.com/dav/calendars/user/personal/").unwrap();
+    let todos = parse_calendar_data(&base_url, &multistatus);
+
+    assert_eq!(1, todos.len());
+    assert_eq!(
+        "https://example.com/dav/calendars/user/personal/todo-1.ics",
+        todos[0].url.as_str()
+    );
+    assert_eq!(Some("\"1234\""), todos[0].etag.as_deref());
+    let calendar_object = minicaldav::parse_ical(&todos[0].data).unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+    let task = CalDavSource::default()
+        .create_task("Personal", &calendar_object, now)
+        .unwrap()
+        .unwrap();
+    assert_eq!("Open todo", task.title);
+}
+
+#[test]
+fn test_sync_collection_applies_changes_and_removals() {
+    let base_url = Url::parse("https://example.com/dav/calendars/user/personal/").unwrap();
+    let initial = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/dav/calendars/user/personal/a.ics</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>"1"</d:getetag>
+        <cal:calendar-data>BEGIN:VCALENDAR
+END:VCALENDAR
+</cal:calendar-data>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/dav/calendars/user/personal/b.ics</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>"2"</d:getetag>
+        <cal:calendar-data>BEGIN:VCALENDAR
+END:VCALENDAR
+</cal:calendar-data>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:sync-token>http://example.com/sync/1</d:sync-token>
+</d:multistatus>"#;
+    let update = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/dav/calendars/user/personal/a.ics</d:href>
+    <d:status>HTTP/1.1 404 Not Found</d:status>
+  </d:response>
+  <d:response>
+    <d:href>/dav/calendars/user/personal/b.ics</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getetag>"3"</d:getetag>
+        <cal:calendar-data>BEGIN:VCALENDAR
+END:VCALENDAR
+</cal:calendar-data>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:sync-token>http://example.com/sync/2</d:sync-token>
+</d:multistatus>"#;
+
+    let mut state = CalendarSyncState::default();
+    let changes =
+        parse_sync_response(&base_url, &Element::parse(initial.as_bytes()).unwrap()).unwrap();
+    state.apply(changes);
+    assert_eq!(Some("http://example.com/sync/1"), state.token.as_deref());
+    assert_eq!(2, state.objects().len());
+
+    let changes =
+        parse_sync_response(&base_url, &Element::parse(update.as_bytes()).unwrap()).unwrap();
+    assert_eq!(1, changes.removed.len());
+    state.apply(changes);
+    assert_eq!(Some("http://example.com/sync/2"), state.token.as_deref());
+    let objects = state.objects();
+    assert_eq!(1, objects.len());
+    assert_eq!(Some("\"3\""), objects[0].etag.as_deref());
+}
+
+#[test]
+fn test_sync_collection_without_token_is_unsupported() {
+    let base_url = Url::parse("https://example.com/dav/calendars/user/personal/").unwrap();
+    let response = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/dav/calendars/user/personal/a.ics</d:href>
+    <d:status>HTTP/1.1 200 OK</d:status>
+  </d:response>
+</d:multistatus>"#;
+    assert!(
+        parse_sync_response(&base_url, &Element::parse(response.as_bytes()).unwrap()).is_none()
+    );
+}
+
+#[test]
+fn test_todo_properties_are_mapped_to_task() {
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:properties
+SUMMARY:Prepare workshop
+STATUS:IN-PROCESS
+PRIORITY:2
+PERCENT-COMPLETE:40
+CATEGORIES:Work,Teaching
+CATEGORIES:Room\\, Building A
+LOCATION:Lecture hall
+URL:https://example.com/workshop
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+    let task = CalDavSource::default()
+        .create_task("Work", &todo, now)
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(2), task.priority);
+    assert_eq!(Some(40), task.percent_complete);
+    assert_eq!(vec!["Work", "Teaching", "Room, Building A"], task.labels);
+    assert_eq!(Some("Lecture hall"), task.location.as_deref());
+    assert_eq!(Some("https://example.com/workshop"), task.url.as_deref());
+    assert_eq!(Some("In progress"), task.status.as_deref());
+}
+
+#[test]
+fn test_parent_relationship() {
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:child
+SUMMARY:Write changelog
+RELATED-TO;RELTYPE=SIBLING:other
+RELATED-TO;RELTYPE=PARENT:release
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+    let task = CalDavSource::default()
+        .create_task("Work", &todo, now)
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some("release"), task.parent.as_deref());
+
+    let todo = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:child
+SUMMARY:Tag version
+RELATED-TO:release
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let task = CalDavSource::default()
+        .create_task("Work", &todo, now)
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some("release"), task.parent.as_deref());
+}
+
+#[test]
+fn test_subtask_of_recurring_parent() {
+    let parent = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:weekly-review
+SUMMARY:Weekly review
+DUE:20240108T090000Z
+RRULE:FREQ=WEEKLY
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let child = parse_todo(
+        "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:inbox
+SUMMARY:Empty inbox
+RELATED-TO;RELTYPE=PARENT:weekly-review
+END:VTODO
+END:VCALENDAR
+",
+    );
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    let source = CalDavSource::default();
+    let parent_task = source.create_task("Work", &parent, now).unwrap().unwrap();
+    let child_task = source.create_task("Work", &child, now).unwrap().unwrap();
+    assert_eq!(
+        Some("weekly-review#20240108T090000"),
+        parent_task.id.as_deref()
+    );
+
+    let mut tasks = vec![parent_task, child_task];
+    let task_id_by_uid = [(&parent, &tasks[0]), (&child, &tasks[1])]
+        .into_iter()
+        .map(|(todo, task)| (todo_uid(todo).unwrap(), task.id.clone().unwrap()))
+        .collect();
+    link_parents(&mut tasks, &task_id_by_uid);
+    assert_eq!(Some(tasks[0].get_id()), tasks[1].get_parent_id());
+}
+
+fn calendar(path: &str, name: &str) -> CalendarRef {
+    CalendarRef {
+        url: Url::parse(&format!("https://example.com/dav/calendars/user/{path}/")).unwrap(),
+        name: name.to_string(),
+        color: None,
+    }
+}
+
+#[test]
+fn test_calendars_are_selected_by_url() {
+    let work = calendar("work", "Work");
+    let other_work = calendar("work-2", "Work");
+    let source = CalDavSource {
+        calendars: BTreeMap::from([(work.url.to_string(), "Work".to_string())]),
+        ..Default::default()
+    };
+    assert!(source.is_included(&work));
+    // Calendars with the same name are different calendars
+    assert!(!source.is_included(&other_work));
+    // Renamed calendars stay selected
+    assert!(source.is_included(&calendar("work", "Office")));
+
+    // Without a selection, all calendars are included
+    let source = CalDavSource::default();
+    assert!(source.is_included(&work));
+    assert!(source.is_included(&calendar("private", "Private")));
+}
+
+#[test]
+fn test_legacy_calendar_name_is_migrated() {
+    let mut source = CalDavSource {
+        legacy_calendar_name: Some("Work".to_string()),
+        ..Default::default()
+    };
+    source.migrate_legacy_calendar();
+    assert_eq!("Work", source.name);
+    assert!(source.is_included(&calendar("work", "Work")));
+    assert!(!source.is_included(&calendar("private", "Private")));
+
+    // Renaming the source does not change the included calendars
+    source.name = "Office".to_string();
+    source.migrate_legacy_calendar();
+    assert_eq!("Office", source.name);
+    assert!(source.is_included(&calendar("work", "Work")));
+
+    // Calendars found by a refresh are selected by their URL
+    let mut refreshed = source.clone();
+    let work_url = calendar("work", "Work").url.to_string();
+    source
+        .resolved_calendars
+        .lock()
+        .unwrap()
+        .insert(work_url.clone(), "Work".to_string());
+    refreshed.migrate_legacy_calendar();
+    assert_eq!(None, refreshed.unresolved_calendar);
+    assert!(refreshed.calendars.contains_key(&work_url));
+
+    // The name is replaced by the URL once the calendar has been found
+    source.resolve_calendar_name(&[
+        (calendar("work", "Work").url.to_string(), "Work".to_string()),
+        (
+            calendar("private", "Private").url.to_string(),
+            "Private".to_string(),
+        ),
+    ]);
+    assert_eq!(None, source.unresolved_calendar);
+    assert!(source.is_included(&calendar("work", "Renamed")));
+    assert!(!source.is_included(&calendar("private", "Private")));
+}
+
+#[test]
+fn test_due_date_limit_includes_todos_without_due_date() {
+    let due_before = Utc.with_ymd_and_hms(2024, 5, 8, 12, 0, 0).unwrap();
+    let filter = open_todos_filter(Some(due_before));
+    // The time range applies to the whole todo and precedes the property filters
+    let time_range = filter.find(r#"<c:time-range end="20240508T120000Z" />"#);
+    assert!(time_range.is_some());
+    assert!(time_range < filter.find("<c:prop-filter"));
+    assert!(!open_todos_filter(None).contains("time-range"));
+
+    assert!(is_due_before(None, Some(due_before)));
+    assert!(is_due_before(
+        Some(due_before - chrono::TimeDelta::days(1)),
+        Some(due_before)
+    ));
+    assert!(!is_due_before(Some(due_before), Some(due_before)));
+    assert!(is_due_before(Some(due_before), None));
+}
+
+#[test]
+fn test_sync_collection_is_retried_after_temporary_errors() {
+    let mut server = mockito::Server::new();
+    let calendar = CalendarRef {
+        url: Url::parse(&format!("{}/dav/calendars/user/personal/", server.url())).unwrap(),
+        name: "Personal".to_string(),
+        color: None,
+    };
+    let credentials = Credentials::Basic("user".to_string(), "secret".to_string());
+    let source = CalDavSource::default();
+
+    // A server error does not disable the synchronization
+    let unavailable = server
+        .mock("REPORT", "/dav/calendars/user/personal/")
+        .with_status(503)
+        .create();
+    assert!(source.get_todos(&credentials, &calendar).is_err());
+    unavailable.assert();
+    unavailable.remove();
+
+    let synchronized = server
+        .mock("REPORT", "/dav/calendars/user/personal/")
+        .match_header("Depth", "0")
+        .with_body(
+            r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:sync-token>http://example.com/sync/1</d:sync-token>
+</d:multistatus>"#,
+        )
+        .create();
+    assert!(source
+        .get_todos(&credentials, &calendar)
+        .unwrap()
+        .is_empty());
+    synchronized.assert();
+    let sync_state = source.sync_state.lock().unwrap();
+    let state = &sync_state[&calendar.url];
+    assert!(!state.unsupported);
+    assert_eq!(Some("http://example.com/sync/1"), state.token.as_deref());
+}
+
+#[test]
+fn test_sync_collection_not_allowed_is_unsupported() {
+    let mut server = mockito::Server::new();
+    let calendar = CalendarRef {
+        url: Url::parse(&format!("{}/dav/calendars/user/personal/", server.url())).unwrap(),
+        name: "Personal".to_string(),
+        color: None,
+    };
+    let credentials = Credentials::Basic("user".to_string(), "secret".to_string());
+    let source = CalDavSource::default();
+
+    server
+        .mock("REPORT", "/dav/calendars/user/personal/")
+        .match_header("Depth", "0")
+        .with_status(405)
+        .create();
+    server
+        .mock("REPORT", "/dav/calendars/user/personal/")
+        .match_header("Depth", "1")
+        .with_body(r#"<?xml version="1.0"?><d:multistatus xmlns:d="DAV:" />"#)
+        .create();
+    assert!(source
+        .get_todos(&credentials, &calendar)
+        .unwrap()
+        .is_empty());
+    assert!(source.sync_state.lock().unwrap()[&calendar.url].unsupported);
+}
+
+#[test]
+fn test_todo_with_invalid_rrule_is_skipped() {
+    let todo = |uid: &str, rrule: &str| EventRef {
+        etag: None,
+        url: Url::parse(&format!("https://example.com/dav/{uid}.ics")).unwrap(),
+        data: format!(
+            "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTODO\r
+UID:{uid}\r
+SUMMARY:Todo {uid}\r
+DTSTART:20240101T090000Z\r
+{rrule}END:VTODO\r
+END:VCALENDAR\r
+"
+        ),
+    };
+    let todos = vec![
+        todo("first", ""),
+        todo("hourly", "RRULE:FREQ=HOURLY\r\n"),
+        todo("malformed", "RRULE:FREQ=WEEKLY;BYDAY=x€\r\n"),
+        todo("last", "RRULE:FREQ=WEEKLY\r\n"),
+    ];
+    let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+    let mut task_id_by_uid = HashMap::new();
+    let tasks =
+        CalDavSource::default().create_calendar_tasks("Work", &todos, now, &mut task_id_by_uid);
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(vec!["Todo first", "Todo last"], titles);
+}
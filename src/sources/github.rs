@@ -1,98 +1,491 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use json::JsonValue;
 use serde::{Deserialize, Serialize};
 use ureq::Agent;
+use uuid::Uuid;
+
+use crate::tasks::{Task, TaskKind, TaskReason};
+
+use super::{
+    next_page_url,
+    oauth::{access_token, DeviceFlow},
+    SourceKind, MAX_PAGES,
+};
+pub use project::ProjectSettings;
+use project::{graphql_url, parse_project_items, ProjectItem};
+
+mod project;
+
+/// Which issues of the user are returned by the `/issues` endpoint.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IssueFilter {
+    #[default]
+    Assigned,
+    Created,
+    Mentioned,
+    Subscribed,
+    All,
+}
 
-use crate::tasks::Task;
+impl IssueFilter {
+    pub const ALL: [IssueFilter; 5] = [
+        IssueFilter::Assigned,
+        IssueFilter::Created,
+        IssueFilter::Mentioned,
+        IssueFilter::Subscribed,
+        IssueFilter::All,
+    ];
+
+    /// Value of the `filter` query parameter.
+    fn parameter(&self) -> &'static str {
+        match self {
+            IssueFilter::Assigned => "assigned",
+            IssueFilter::Created => "created",
+            IssueFilter::Mentioned => "mentioned",
+            IssueFilter::Subscribed => "subscribed",
+            IssueFilter::All => "all",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IssueFilter::Assigned => "Assigned to me",
+            IssueFilter::Created => "Created by me",
+            IssueFilter::Mentioned => "Mentioning me",
+            IssueFilter::Subscribed => "Subscribed",
+            IssueFilter::All => "All visible",
+        }
+    }
+}
 
-use super::GITHUB_ICON;
+/// Split a comma separated list and ignore empty entries.
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(|e| e.trim()).filter(|e| !e.is_empty())
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct GitHubSource {
     #[serde(skip)]
     agent: ureq::Agent,
+    /// Stable identifier of this source, which does not change when the
+    /// source is renamed. Sources of older versions have no ID, which is
+    /// read as nil ID and replaced by [`TaskSource::migrate_legacy_settings`].
+    #[serde(default)]
+    pub id: Uuid,
     pub name: String,
     pub server_url: String,
+    /// Client ID of the OAuth app used to log in with the device flow.
+    pub oauth_client_id: String,
+    pub filter: IssueFilter,
+    /// Comma separated list of labels the issues must have.
+    pub labels: String,
+    /// Comma separated list of organizations to restrict the issues to.
+    pub organizations: String,
+    /// Search query (e.g. `is:open review-requested:@me`), which is used
+    /// instead of the filter, labels and organizations when set.
+    pub search_query: String,
+    /// Include pull requests where the review of the user or one of their
+    /// teams is requested.
+    pub review_requests: bool,
+    pub exclude_draft_reviews: bool,
+    /// Take due dates and status from a project, instead of milestones.
+    pub project: ProjectSettings,
+    /// When the review of each pull request was last requested, which is
+    /// shared between all clones of this source.
+    #[serde(skip)]
+    review_requests_cache: Arc<Mutex<HashMap<u64, ReviewRequest>>>,
+}
+
+/// The time a review of a pull request was last requested, taken from its
+/// timeline. It only needs to be fetched again when the pull request has been
+/// updated since.
+#[derive(Clone, Debug)]
+struct ReviewRequest {
+    updated_at: String,
+    requested_at: Option<DateTime<Utc>>,
 }
 
 impl Default for GitHubSource {
     fn default() -> Self {
         Self {
             agent: Agent::new(),
+            id: Uuid::new_v4(),
             name: "GitHub".to_string(),
             server_url: "https://api.github.com".to_string(),
+            oauth_client_id: String::default(),
+            filter: IssueFilter::default(),
+            labels: String::default(),
+            organizations: String::default(),
+            search_query: String::default(),
+            review_requests: false,
+            exclude_draft_reviews: true,
+            project: ProjectSettings::default(),
+            review_requests_cache: Arc::default(),
         }
     }
 }
+/// The last time a review was requested, according to the timeline events of
+/// a pull request.
+fn last_review_request(timeline: &[JsonValue]) -> Option<DateTime<Utc>> {
+    timeline
+        .iter()
+        .filter(|event| event["event"] == "review_requested")
+        .filter_map(|event| parse_date(&event["created_at"]).ok().flatten())
+        .max()
+}
+
+/// Parse an optional date in the ISO 8601 format used by GitHub.
+fn parse_date(value: &JsonValue) -> Result<Option<DateTime<Utc>>> {
+    let result = value
+        .as_str()
+        .map(|d| DateTime::parse_from_str(d, "%+"))
+        .transpose()?
+        .map(|d| d.into());
+    Ok(result)
+}
+
 impl GitHubSource {
-    pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
-    where
-        S: AsRef<str>,
-    {
-        let mut result = Vec::default();
+    pub fn device_flow(&self) -> DeviceFlow {
+        DeviceFlow::github(&self.server_url, &self.oauth_client_id)
+    }
+
+    fn create_task(&self, issue: &JsonValue) -> Result<Option<Task>> {
+        if Some("open") != issue["state"].as_str() {
+            return Ok(None);
+        }
+        let project = if let JsonValue::Object(repo) = &issue["repository"] {
+            repo["full_name"]
+                .as_str()
+                .context("Missing 'full_name' field for issue")?
+                .to_string()
+        } else if let Some(repository_url) = issue["repository_url"].as_str() {
+            // Search results only contain the API URL of the repository, which
+            // ends with the full name
+            let mut parts = repository_url.rsplit('/');
+            match (parts.next(), parts.next()) {
+                (Some(repo), Some(owner)) => format!("{owner}/{repo}"),
+                _ => "GitHub".to_string(),
+            }
+        } else {
+            "GitHub".to_string()
+        };
+
+        let title = issue["title"]
+            .as_str()
+            .context("Missing 'title' field for issue")?;
+        let url = issue["html_url"]
+            .as_str()
+            .context("Missing 'html_url' field for issue")?;
+
+        let due = if let JsonValue::Object(milestone) = &issue["milestone"] {
+            parse_date(&milestone["due_on"])?
+        } else {
+            None
+        };
+
+        // Pull requests are also returned as issues
+        let kind = if issue["pull_request"].is_object() {
+            TaskKind::PullRequest
+        } else {
+            TaskKind::Issue
+        };
 
+        let task = Task {
+            project,
+            title: title.to_string(),
+            description: issue["body"].as_str().unwrap_or_default().to_string(),
+            markdown: true,
+            due,
+            created: parse_date(&issue["created_at"])?,
+            // The numeric ID stays the same when the repository is renamed
+            id: Some(
+                issue["id"]
+                    .as_u64()
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| url.to_string()),
+            ),
+            source_id: self.id,
+            source_name: self.name.clone(),
+            source_kind: Some(SourceKind::GitHub),
+            kind,
+            updated: parse_date(&issue["updated_at"])?,
+            assignees: issue["assignees"]
+                .members()
+                .filter_map(|a| a["login"].as_str())
+                .map(|a| a.to_string())
+                .collect(),
+            author: issue["user"]["login"].as_str().map(|a| a.to_string()),
+            labels: issue["labels"]
+                .members()
+                .filter_map(|l| l["name"].as_str())
+                .map(|l| l.to_string())
+                .collect(),
+            url: Some(url.to_string()),
+            ..Default::default()
+        };
+        Ok(Some(task))
+    }
+
+    /// URLs of all queries that are needed for the configured issues.
+    fn query_urls(&self) -> Vec<String> {
+        let server_url = self.server_url.trim_end_matches('/');
+        let search_query = self.search_query.trim();
+        if !search_query.is_empty() {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("q", search_query)
+                .append_pair("per_page", "100")
+                .finish();
+            return vec![format!("{server_url}/search/issues?{query}")];
+        }
+
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query
+            .append_pair("filter", self.filter.parameter())
+            .append_pair("state", "open")
+            .append_pair("per_page", "100");
+        let labels: Vec<&str> = split_list(&self.labels).collect();
+        if !labels.is_empty() {
+            query.append_pair("labels", &labels.join(","));
+        }
+        let query = query.finish();
+
+        let organizations: Vec<&str> = split_list(&self.organizations).collect();
+        if organizations.is_empty() {
+            vec![format!("{server_url}/issues?{query}")]
+        } else {
+            organizations
+                .into_iter()
+                .map(|org| format!("{server_url}/orgs/{org}/issues?{query}"))
+                .collect()
+        }
+    }
+
+    fn get(&self, url: &str, secret: Option<&str>) -> Result<ureq::Response> {
         let mut request = self
             .agent
-            .get(&format!("{}/issues", self.server_url))
+            .get(url)
             .set("X-GitHub-Api-Version", "2022-11-28")
             .set("Accept", "application/vnd.github+json");
         if let Some(secret) = secret {
-            request = request.set("Authorization", &format!("Bearer {}", secret.as_ref()))
-        }
-        let response = request.call()?;
-        let body = response.into_string()?;
-        let assigned_issues = json::parse(&body)?;
-        if let JsonValue::Array(assigned_issues) = assigned_issues {
-            for issue in assigned_issues {
-                if let JsonValue::Object(issue) = issue {
-                    if Some("open") == issue["state"].as_str() {
-                        let project = if let JsonValue::Object(repo) = &issue["repository"] {
-                            repo["full_name"]
-                                .as_str()
-                                .context("Missing 'full_name' field for issue")?
-                        } else {
-                            "GitHub"
-                        };
-
-                        let title = issue["title"]
-                            .as_str()
-                            .context("Missing 'title' field for issue")?;
-                        let url = issue["html_url"]
-                            .as_str()
-                            .context("Missing 'html_url' field for issue")?;
-
-                        let created: Option<DateTime<Utc>> = issue["created_at"]
-                            .as_str()
-                            .map(|d| DateTime::parse_from_str(d, "%+"))
-                            .transpose()?
-                            .map(|d| d.into());
-
-                        let due: Option<DateTime<Utc>> =
-                            if let JsonValue::Object(milestone) = &issue["milestone"] {
-                                milestone["due_on"]
-                                    .as_str()
-                                    .map(|d| DateTime::parse_from_str(d, "%+"))
-                                    .transpose()?
-                                    .map(|d| d.into())
-                            } else {
-                                None
-                            };
-
-                        let task = Task {
-                            project: format!("{} {}", GITHUB_ICON, project),
-                            title: title.to_string(),
-                            description: url.to_string(),
-                            due,
-                            created,
-                            id: Some(url.to_string()),
-                        };
-                        result.push(task);
-                    }
+            let token = access_token(secret);
+            request = request.set("Authorization", &format!("Bearer {token}"))
+        }
+        Ok(request.call()?)
+    }
+
+    /// Get all entries of a paginated list.
+    fn get_all_pages(&self, url: String, secret: Option<&str>) -> Result<Vec<JsonValue>> {
+        let mut result = Vec::default();
+        let mut next_url = Some(url);
+        let mut page = 0;
+        while let Some(url) = next_url.take() {
+            let response = self.get(&url, secret)?;
+            page += 1;
+            if page < MAX_PAGES {
+                next_url = response.header("link").and_then(next_page_url);
+            }
+            let mut body = json::parse(&response.into_string()?)?;
+            // The search API wraps the entries in an object
+            if body.is_object() {
+                body = body["items"].take();
+            }
+            result.extend(body.members_mut().map(|e| e.take()));
+        }
+        Ok(result)
+    }
+
+    /// Search query for the pull requests that wait for a review by the user.
+    /// This includes review requests for teams of the user.
+    fn review_requests_query(&self) -> String {
+        let mut query = "is:open is:pr archived:false review-requested:@me".to_string();
+        if self.exclude_draft_reviews {
+            query.push_str(" draft:false");
+        }
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", &query)
+            .append_pair("per_page", "100")
+            .finish();
+        format!(
+            "{}/search/issues?{query}",
+            self.server_url.trim_end_matches('/')
+        )
+    }
+
+    /// Create a task for a pull request from the search results. The review
+    /// has been waiting since the last time it was requested.
+    fn create_review_task(
+        &self,
+        pull_request: &JsonValue,
+        requested_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Task>> {
+        let Some(mut task) = self.create_task(pull_request)? else {
+            return Ok(None);
+        };
+        task.kind = TaskKind::ReviewRequest;
+        // The number is shown with the reason, so the title stays the same
+        // for sorting and searching
+        task.reason = Some(TaskReason {
+            action: "review_requested".to_string(),
+            actor: None,
+            target_type: Some("PullRequest".to_string()),
+            target_reference: pull_request["number"]
+                .as_u64()
+                .map(|number| format!("#{number}")),
+        });
+        if requested_at.is_some() {
+            task.created = requested_at;
+        }
+        Ok(Some(task))
+    }
+
+    /// The time the review of the pull request was last requested. The
+    /// timeline of the pull request is only requested if it is not cached for
+    /// the current state of the pull request.
+    fn review_requested_at(
+        &self,
+        pull_request: &JsonValue,
+        secret: Option<&str>,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let (Some(id), Some(repository_url), Some(number)) = (
+            pull_request["id"].as_u64(),
+            pull_request["repository_url"].as_str(),
+            pull_request["number"].as_u64(),
+        ) else {
+            return Ok(None);
+        };
+        let updated_at = pull_request["updated_at"].as_str().unwrap_or_default();
+        if let Some(cached) = self
+            .review_requests_cache
+            .lock()
+            .expect("Lock poisoning")
+            .get(&id)
+            .filter(|cached| cached.updated_at == updated_at)
+        {
+            return Ok(cached.requested_at);
+        }
+        let timeline = self.get_all_pages(
+            format!("{repository_url}/issues/{number}/timeline?per_page=100"),
+            secret,
+        )?;
+        let requested_at = last_review_request(&timeline);
+        self.review_requests_cache
+            .lock()
+            .expect("Lock poisoning")
+            .insert(
+                id,
+                ReviewRequest {
+                    updated_at: updated_at.to_string(),
+                    requested_at,
+                },
+            );
+        Ok(requested_at)
+    }
+
+    fn query_review_requests(&self, secret: Option<&str>) -> Result<Vec<Task>> {
+        let mut result = Vec::default();
+        let mut ids = HashSet::new();
+        for pull_request in self.get_all_pages(self.review_requests_query(), secret)? {
+            if self.exclude_draft_reviews && pull_request["draft"].as_bool() == Some(true) {
+                continue;
+            }
+            ids.extend(pull_request["id"].as_u64());
+            let requested_at = self.review_requested_at(&pull_request, secret)?;
+            if let Some(task) = self.create_review_task(&pull_request, requested_at)? {
+                result.push(task);
+            }
+        }
+        // Forget pull requests that are not waiting for a review anymore
+        self.review_requests_cache
+            .lock()
+            .expect("Lock poisoning")
+            .retain(|id, _| ids.contains(id));
+        Ok(result)
+    }
+
+    /// Get the field values of all items of the configured project.
+    fn query_project_items(&self, secret: Option<&str>) -> Result<HashMap<String, ProjectItem>> {
+        let secret = secret.context("Querying GitHub projects requires a token")?;
+        let url = graphql_url(&self.server_url);
+        let mut result = HashMap::new();
+        let mut cursor = None;
+        for _ in 0..MAX_PAGES {
+            let response = self
+                .agent
+                .post(&url)
+                .set("Authorization", &format!("Bearer {}", access_token(secret)))
+                .send_string(&self.project.request(cursor.as_deref()))?;
+            let response = json::parse(&response.into_string()?)?;
+            let (items, next_cursor) = parse_project_items(&response, &self.project)?;
+            result.extend(items);
+            cursor = next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Use the due date and status of the project items for the tasks and
+    /// remove tasks that are done.
+    fn apply_project_items(&self, tasks: &mut Vec<Task>, items: &HashMap<String, ProjectItem>) {
+        tasks.retain_mut(|task| {
+            let item = task.url.as_ref().and_then(|url| items.get(url));
+            if let Some(item) = item {
+                if item
+                    .status
+                    .as_ref()
+                    .is_some_and(|s| self.project.is_done(s))
+                {
+                    return false;
                 }
+                task.due = item.due.or(task.due);
+                task.status = item.status.clone();
+            }
+            true
+        });
+    }
+
+    pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
+    where
+        S: AsRef<str>,
+    {
+        let secret = secret.as_ref().map(|s| s.as_ref());
+        let mut result = Vec::default();
+        let mut seen_ids = HashSet::new();
+
+        // Review requests are added first, so a pull request that is also
+        // returned as issue is shown as review request
+        let mut tasks = if self.review_requests {
+            self.query_review_requests(secret)?
+        } else {
+            Vec::default()
+        };
+        for url in self.query_urls() {
+            for issue in self.get_all_pages(url, secret)? {
+                if let Some(task) = self.create_task(&issue)? {
+                    tasks.push(task);
+                }
+            }
+        }
+        for task in tasks {
+            if seen_ids.insert(task.get_id()) {
+                result.push(task);
             }
         }
+        if self.project.is_enabled() {
+            let items = self.query_project_items(secret)?;
+            self.apply_project_items(&mut result, &items);
+        }
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests;
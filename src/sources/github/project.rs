@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use json::{object, JsonValue};
+use serde::{Deserialize, Serialize};
+
+/// Query the items of a project with the values of their fields. Projects can
+/// belong to organizations and users, which both are repository owners.
+const PROJECT_ITEMS_QUERY: &str = r#"
+query($owner: String!, $number: Int!, $cursor: String) {
+  repositoryOwner(login: $owner) {
+    ... on ProjectV2Owner {
+      projectV2(number: $number) {
+        items(first: 100, after: $cursor) {
+          pageInfo { hasNextPage endCursor }
+          nodes {
+            content {
+              ... on Issue { url }
+              ... on PullRequest { url }
+            }
+            fieldValues(first: 50) {
+              nodes {
+                ... on ProjectV2ItemFieldDateValue {
+                  date
+                  field { ... on ProjectV2FieldCommon { name } }
+                }
+                ... on ProjectV2ItemFieldIterationValue {
+                  startDate
+                  duration
+                  field { ... on ProjectV2FieldCommon { name } }
+                }
+                ... on ProjectV2ItemFieldSingleSelectValue {
+                  name
+                  field { ... on ProjectV2FieldCommon { name } }
+                }
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+/// A GitHub project (v2) from which the due date and status of issues are
+/// taken.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProjectSettings {
+    /// Login of the organization or user the project belongs to.
+    pub owner: String,
+    /// Number of the project. Projects are not queried if this is 0.
+    pub number: u32,
+    /// Name of a date field that is used as due date.
+    pub due_field: String,
+    /// Name of an iteration field. The end of the iteration is used as due
+    /// date, if there is no due date field.
+    pub iteration_field: String,
+    /// Name of a single select field that is shown as status.
+    pub status_field: String,
+    /// Items with this status are hidden.
+    pub done_status: String,
+}
+
+impl Default for ProjectSettings {
+    fn default() -> Self {
+        Self {
+            owner: String::default(),
+            number: 0,
+            due_field: "Due".to_string(),
+            iteration_field: "Iteration".to_string(),
+            status_field: "Status".to_string(),
+            done_status: "Done".to_string(),
+        }
+    }
+}
+
+impl ProjectSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.number > 0 && !self.owner.trim().is_empty()
+    }
+
+    pub fn is_done(&self, status: &str) -> bool {
+        status.eq_ignore_ascii_case(self.done_status.trim())
+    }
+
+    /// Create the body of the GraphQL request for a page of items.
+    pub fn request(&self, cursor: Option<&str>) -> String {
+        let request = object! {
+            query: PROJECT_ITEMS_QUERY,
+            variables: {
+                owner: self.owner.trim(),
+                number: self.number,
+                cursor: cursor,
+            }
+        };
+        request.dump()
+    }
+}
+
+/// Values of the project fields for an issue or pull request.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectItem {
+    pub due: Option<DateTime<Utc>>,
+    pub status: Option<String>,
+}
+
+fn parse_day(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+fn day_to_utc(day: NaiveDate) -> Option<DateTime<Utc>> {
+    day.and_hms_opt(0, 0, 0)
+        .map(|d| DateTime::from_naive_utc_and_offset(d, Utc))
+}
+
+/// Get the GraphQL endpoint for the URL of the REST API.
+pub fn graphql_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    if let Some(server) = api_url.strip_suffix("/api/v3") {
+        // GitHub Enterprise Server
+        format!("{server}/api/graphql")
+    } else {
+        format!("{api_url}/graphql")
+    }
+}
+
+/// Parse a page of project items. Returns the items by the URL of the issue or
+/// pull request, and the cursor of the next page. The URL is used because
+/// the database ID of a pull request differs from its ID as an issue.
+pub fn parse_project_items(
+    response: &JsonValue,
+    settings: &ProjectSettings,
+) -> Result<(HashMap<String, ProjectItem>, Option<String>)> {
+    if let Some(error) = response["errors"].members().next() {
+        bail!(
+            "GitHub project query failed: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        );
+    }
+    let project = &response["data"]["repositoryOwner"]["projectV2"];
+    if project.is_null() {
+        bail!(
+            "GitHub project {} of {} not found",
+            settings.number,
+            settings.owner
+        );
+    }
+    let items = &project["items"];
+
+    let mut result = HashMap::new();
+    for node in items["nodes"].members() {
+        // Draft issues have no URL
+        let Some(url) = node["content"]["url"].as_str() else {
+            continue;
+        };
+        let mut item = ProjectItem::default();
+        let mut iteration_end = None;
+        for value in node["fieldValues"]["nodes"].members() {
+            let field = value["field"]["name"].as_str().unwrap_or_default();
+            if field == settings.due_field {
+                item.due = value["date"]
+                    .as_str()
+                    .and_then(parse_day)
+                    .and_then(day_to_utc);
+            } else if field == settings.iteration_field {
+                // The due date is the last day of the iteration
+                let duration = value["duration"].as_u64().unwrap_or(1).max(1);
+                iteration_end = value["startDate"]
+                    .as_str()
+                    .and_then(parse_day)
+                    .and_then(|start| start.checked_add_days(Days::new(duration - 1)))
+                    .and_then(day_to_utc);
+            } else if field == settings.status_field {
+                item.status = value["name"].as_str().map(|s| s.to_string());
+            }
+        }
+        item.due = item.due.or(iteration_end);
+        result.insert(url.to_string(), item);
+    }
+
+    let next_cursor = if items["pageInfo"]["hasNextPage"].as_bool() == Some(true) {
+        Some(
+            items["pageInfo"]["endCursor"]
+                .as_str()
+                .context("Missing cursor for next page of project items")?
+                .to_string(),
+        )
+    } else {
+        None
+    };
+    Ok((result, next_cursor))
+}
@@ -0,0 +1,341 @@
+use chrono::{TimeZone, Utc};
+
+use crate::tasks::{Task, TaskKind};
+
+use super::{
+    last_review_request, next_page_url,
+    project::{graphql_url, parse_project_items, ProjectItem},
+    GitHubSource, IssueFilter, ProjectSettings,
+};
+
+#[test]
+fn parse_github_issue() {
+    let json_body = r#"
+    {
+        "html_url": "https://github.com/octocat/Hello-World/issues/1347",
+        "number": 1347,
+        "state": "open",
+        "title": "Found a bug",
+        "body": "I'm having a problem with this.",
+        "labels": [{"name": "bug"}],
+        "assignees": [{"login": "octocat"}, {"login": "hubot"}],
+        "milestone": {"due_on": "2012-10-09T23:39:01Z"},
+        "created_at": "2011-04-22T13:33:48Z",
+        "updated_at": "2011-04-22T13:33:48Z",
+        "repository": {"full_name": "octocat/Hello-World"}
+    }
+    "#;
+    let issue = json::parse(json_body).unwrap();
+    let source = GitHubSource::default();
+
+    let task = source.create_task(&issue).unwrap().unwrap();
+    assert_eq!("Found a bug", task.title);
+    assert_eq!("octocat/Hello-World", task.project);
+    assert_eq!("I'm having a problem with this.", task.description);
+    assert_eq!(
+        Some("https://github.com/octocat/Hello-World/issues/1347"),
+        task.url.as_deref()
+    );
+    assert_eq!(TaskKind::Issue, task.kind);
+    assert_eq!(vec!["bug"], task.labels);
+    assert_eq!(vec!["octocat", "hubot"], task.assignees);
+    assert_eq!("GitHub", task.source_name);
+    assert!(task.due.is_some());
+}
+
+#[test]
+fn parse_github_search_result() {
+    // Search results reference the repository only by its API URL
+    let json_body = r#"
+    {
+        "id": 1,
+        "html_url": "https://github.com/octocat/Hello-World/pull/1348",
+        "state": "open",
+        "title": "Fix the bug",
+        "repository_url": "https://api.github.com/repos/octocat/Hello-World",
+        "pull_request": {"url": "https://api.github.com/repos/octocat/Hello-World/pulls/1348"},
+        "created_at": "2011-04-22T13:33:48Z"
+    }
+    "#;
+    let issue = json::parse(json_body).unwrap();
+    let task = GitHubSource::default()
+        .create_task(&issue)
+        .unwrap()
+        .unwrap();
+    assert_eq!("octocat/Hello-World", task.project);
+    assert_eq!(TaskKind::PullRequest, task.kind);
+}
+
+#[test]
+fn github_query_urls() {
+    let mut source = GitHubSource {
+        filter: IssueFilter::Created,
+        labels: "bug, help wanted".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        vec![
+            "https://api.github.com/issues?filter=created&state=open&per_page=100&labels=bug%2Chelp+wanted"
+        ],
+        source.query_urls()
+    );
+
+    source.organizations = "rust-lang, ,octo-org".to_string();
+    assert_eq!(
+        vec![
+            "https://api.github.com/orgs/rust-lang/issues?filter=created&state=open&per_page=100&labels=bug%2Chelp+wanted",
+            "https://api.github.com/orgs/octo-org/issues?filter=created&state=open&per_page=100&labels=bug%2Chelp+wanted"
+        ],
+        source.query_urls()
+    );
+
+    // A search query replaces all other options
+    source.search_query = "is:open review-requested:@me".to_string();
+    assert_eq!(
+        vec!["https://api.github.com/search/issues?q=is%3Aopen+review-requested%3A%40me&per_page=100"],
+        source.query_urls()
+    );
+}
+
+#[test]
+fn github_next_page_url() {
+    let link = r#"<https://api.github.com/issues?page=2>; rel="next", <https://api.github.com/issues?page=5>; rel="last""#;
+    assert_eq!(
+        Some("https://api.github.com/issues?page=2".to_string()),
+        next_page_url(link)
+    );
+    assert_eq!(
+        None,
+        next_page_url(r#"<https://api.github.com/issues?page=1>; rel="prev""#)
+    );
+}
+
+#[test]
+fn parse_github_review_request() {
+    let json_body = r#"
+    {
+        "id": 2,
+        "number": 1348,
+        "html_url": "https://github.com/octocat/Hello-World/pull/1348",
+        "state": "open",
+        "draft": false,
+        "title": "Fix the bug",
+        "user": {"login": "hubot"},
+        "repository_url": "https://api.github.com/repos/octocat/Hello-World",
+        "pull_request": {"url": "https://api.github.com/repos/octocat/Hello-World/pulls/1348"},
+        "created_at": "2011-04-22T13:33:48Z"
+    }
+    "#;
+    let timeline = json::parse(
+        r#"[
+        {"event": "review_requested", "created_at": "2011-04-23T10:00:00Z"},
+        {"event": "commented", "created_at": "2011-04-25T10:00:00Z"},
+        {"event": "review_requested", "created_at": "2011-04-24T10:00:00Z"}
+    ]"#,
+    )
+    .unwrap();
+    let timeline: Vec<_> = timeline.members().cloned().collect();
+    let pull_request = json::parse(json_body).unwrap();
+    let task = GitHubSource::default()
+        .create_review_task(&pull_request, last_review_request(&timeline))
+        .unwrap()
+        .unwrap();
+    assert_eq!(TaskKind::ReviewRequest, task.kind);
+    assert_eq!("Fix the bug", task.title);
+    assert_eq!(
+        Some("#1348"),
+        task.reason
+            .as_ref()
+            .and_then(|reason| reason.target_reference.as_deref())
+    );
+    assert_eq!("octocat/Hello-World", task.project);
+    assert_eq!(Some("hubot"), task.author.as_deref());
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2011, 4, 24, 10, 0, 0).unwrap()),
+        task.created
+    );
+}
+
+#[test]
+fn github_review_request_timeline_is_cached() {
+    let mut server = mockito::Server::new();
+    let source = GitHubSource {
+        server_url: server.url(),
+        ..Default::default()
+    };
+    let server_url = server.url();
+    let search_result = |updated_at: &str| {
+        format!(
+            r#"{{"items": [{{
+                "id": 2,
+                "number": 1348,
+                "html_url": "https://github.com/octocat/Hello-World/pull/1348",
+                "state": "open",
+                "title": "Fix the bug",
+                "repository_url": "{}/repos/octocat/Hello-World",
+                "pull_request": {{}},
+                "created_at": "2011-04-22T13:33:48Z",
+                "updated_at": "{updated_at}"
+            }}]}}"#,
+            server_url
+        )
+    };
+    let search = server
+        .mock("GET", "/search/issues")
+        .match_query(mockito::Matcher::Any)
+        .with_body(search_result("2011-04-23T10:00:00Z"))
+        .create();
+    let timeline = server
+        .mock("GET", "/repos/octocat/Hello-World/issues/1348/timeline")
+        .match_query(mockito::Matcher::Any)
+        .with_body(r#"[{"event": "review_requested", "created_at": "2011-04-23T10:00:00Z"}]"#)
+        .expect(1)
+        .create();
+
+    for _ in 0..2 {
+        let tasks = source.query_review_requests(None).unwrap();
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2011, 4, 23, 10, 0, 0).unwrap()),
+            tasks[0].created
+        );
+    }
+    timeline.assert();
+
+    // An updated pull request is requested again
+    search.remove();
+    server
+        .mock("GET", "/search/issues")
+        .match_query(mockito::Matcher::Any)
+        .with_body(search_result("2011-04-24T10:00:00Z"))
+        .create();
+    let timeline = server
+        .mock("GET", "/repos/octocat/Hello-World/issues/1348/timeline")
+        .match_query(mockito::Matcher::Any)
+        .with_body(r#"[{"event": "review_requested", "created_at": "2011-04-24T10:00:00Z"}]"#)
+        .expect(1)
+        .create();
+    let tasks = source.query_review_requests(None).unwrap();
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2011, 4, 24, 10, 0, 0).unwrap()),
+        tasks[0].created
+    );
+    timeline.assert();
+}
+
+#[test]
+fn github_review_requests_query() {
+    let mut source = GitHubSource::default();
+    assert_eq!(
+        "https://api.github.com/search/issues?q=is%3Aopen+is%3Apr+archived%3Afalse+review-requested%3A%40me+draft%3Afalse&per_page=100",
+        source.review_requests_query()
+    );
+    source.exclude_draft_reviews = false;
+    assert!(!source.review_requests_query().contains("draft"));
+}
+
+#[test]
+fn github_graphql_url() {
+    assert_eq!(
+        "https://api.github.com/graphql",
+        graphql_url("https://api.github.com")
+    );
+    assert_eq!(
+        "https://github.example.com/api/graphql",
+        graphql_url("https://github.example.com/api/v3/")
+    );
+}
+
+#[test]
+fn parse_github_project_items() {
+    let response = json::parse(
+        r#"
+    {"data": {"repositoryOwner": {"projectV2": {"items": {
+        "pageInfo": {"hasNextPage": true, "endCursor": "Y3Vyc29y"},
+        "nodes": [
+            {"content": {"url": "https://github.com/octo-org/app/issues/1"}, "fieldValues": {"nodes": [
+                {},
+                {"date": "2024-05-10", "field": {"name": "Due"}},
+                {"name": "In Progress", "field": {"name": "Status"}}
+            ]}},
+            {"content": {"url": "https://github.com/octo-org/app/issues/2"}, "fieldValues": {"nodes": [
+                {"startDate": "2024-05-06", "duration": 14, "field": {"name": "Iteration"}},
+                {"name": "Done", "field": {"name": "Status"}}
+            ]}},
+            {"content": {"url": "https://github.com/octo-org/app/pull/3"}, "fieldValues": {"nodes": [
+                {"name": "In Review", "field": {"name": "Status"}}
+            ]}},
+            {"content": {}, "fieldValues": {"nodes": []}}
+        ]
+    }}}}}
+    "#,
+    )
+    .unwrap();
+    let settings = ProjectSettings {
+        owner: "octo-org".to_string(),
+        number: 5,
+        ..Default::default()
+    };
+    let (items, cursor) = parse_project_items(&response, &settings).unwrap();
+    assert_eq!(Some("Y3Vyc29y"), cursor.as_deref());
+    assert_eq!(3, items.len());
+    assert_eq!(
+        ProjectItem {
+            due: Some(Utc.with_ymd_and_hms(2024, 5, 10, 0, 0, 0).unwrap()),
+            status: Some("In Progress".to_string()),
+        },
+        items["https://github.com/octo-org/app/issues/1"]
+    );
+    // The last day of the iteration is the due date
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2024, 5, 19, 0, 0, 0).unwrap()),
+        items["https://github.com/octo-org/app/issues/2"].due
+    );
+
+    let source = GitHubSource {
+        project: settings,
+        ..Default::default()
+    };
+    let issue_task = |number: u64| Task {
+        id: Some(number.to_string()),
+        url: Some(format!("https://github.com/octo-org/app/issues/{number}")),
+        ..Default::default()
+    };
+    // Pull requests from the search have an issue ID that differs from the
+    // database ID of the pull request
+    let pull_request = json::parse(
+        r#"
+    {
+        "id": 1003,
+        "number": 3,
+        "html_url": "https://github.com/octo-org/app/pull/3",
+        "state": "open",
+        "title": "Add the feature",
+        "repository_url": "https://api.github.com/repos/octo-org/app",
+        "pull_request": {"url": "https://api.github.com/repos/octo-org/app/pulls/3"}
+    }
+    "#,
+    )
+    .unwrap();
+    let mut tasks = vec![
+        issue_task(1),
+        issue_task(2),
+        source.create_task(&pull_request).unwrap().unwrap(),
+        issue_task(4),
+    ];
+    source.apply_project_items(&mut tasks, &items);
+    let ids: Vec<_> = tasks.iter().filter_map(|t| t.id.as_deref()).collect();
+    assert_eq!(vec!["1", "1003", "4"], ids);
+    assert_eq!(Some("In Progress"), tasks[0].status.as_deref());
+    assert!(tasks[0].due.is_some());
+    assert_eq!(Some("In Review"), tasks[1].status.as_deref());
+    assert_eq!(None, tasks[2].status);
+}
+
+#[test]
+fn github_project_query_errors() {
+    let settings = ProjectSettings::default();
+    let not_found = json::parse(r#"{"data": {"repositoryOwner": null}}"#).unwrap();
+    assert!(parse_project_items(&not_found, &settings).is_err());
+    let error = json::parse(r#"{"errors": [{"message": "Bad credentials"}]}"#).unwrap();
+    assert!(parse_project_items(&error, &settings).is_err());
+}
@@ -1,103 +1,375 @@
-use anyhow::{Ok, Result};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{bail, Context, Ok, Result};
 use chrono::{DateTime, NaiveDate, Utc};
 use json::JsonValue;
 use serde::{Deserialize, Serialize};
 use ureq::Agent;
+use uuid::Uuid;
 
-use crate::tasks::Task;
+use crate::tasks::{Task, TaskKind, TaskReason};
 
-use super::GITLAB_ICON;
+use super::{
+    next_page_url,
+    oauth::{DeviceFlow, OAuthToken},
+    SourceKind, MAX_PAGES,
+};
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct GitLabSource {
     #[serde(skip)]
     agent: Agent,
+    /// Held while the OAuth token is refreshed, shared by all clones of this
+    /// source.
+    #[serde(skip)]
+    refresh_lock: Arc<Mutex<()>>,
+    /// Stable identifier of this source, which does not change when the
+    /// source is renamed. Sources of older versions have no ID, which is
+    /// read as nil ID and replaced by [`TaskSource::migrate_legacy_settings`].
+    #[serde(default)]
+    pub id: Uuid,
     pub name: String,
     pub server_url: String,
+    /// User name or numeric user ID for the merge requests to review. If
+    /// empty, the user of the token is used.
     pub user_name: String,
+    /// Client ID of the OAuth application used to log in with the device
+    /// flow.
+    pub oauth_client_id: String,
+    /// Include pending items of the todo list.
+    pub todos: bool,
+    /// Include open issues assigned to the user.
+    pub assigned_issues: bool,
+    /// Include open merge requests assigned to the user.
+    pub assigned_merge_requests: bool,
+    /// Include open merge requests where the user is a reviewer.
+    pub review_merge_requests: bool,
+    /// Hide todos whose issue or merge request has been closed or merged.
+    /// Otherwise, they are shown with their state.
+    pub hide_closed_todos: bool,
 }
 
 impl Default for GitLabSource {
     fn default() -> Self {
         Self {
             agent: Agent::new(),
+            refresh_lock: Arc::default(),
+            id: Uuid::new_v4(),
             name: "GitLab".to_string(),
             server_url: "https://gitlab.com/api/v4/".to_string(),
             user_name: Default::default(),
+            oauth_client_id: String::default(),
+            todos: true,
+            assigned_issues: false,
+            assigned_merge_requests: false,
+            review_merge_requests: false,
+            hide_closed_todos: true,
         }
     }
 }
 
+/// Parse an optional date in the ISO 8601 format used by GitLab.
+fn parse_date(value: &JsonValue) -> Result<Option<DateTime<Utc>>> {
+    let result = value
+        .as_str()
+        .map(|d| DateTime::parse_from_str(d, "%+"))
+        .transpose()?
+        .map(|d| d.into());
+    Ok(result)
+}
+
+/// Why a todo has been created, e.g. a review requested by Alice on a merge
+/// request.
+fn todo_reason(todo: &JsonValue) -> TaskReason {
+    let action = todo["action_name"].as_str().unwrap_or_default();
+    // Some actions are not triggered by a person
+    let automatic = matches!(
+        action,
+        "build_failed" | "unmergeable" | "merge_train_removed"
+    );
+    let actor = todo["author"]["name"]
+        .as_str()
+        .or_else(|| todo["author"]["username"].as_str())
+        .filter(|_| !automatic);
+
+    let target_type = todo["target_type"].as_str();
+    let iid = todo["target"]["iid"].as_u64();
+    let target_reference = match (target_type, iid) {
+        (Some("MergeRequest"), Some(iid)) => Some(format!("!{iid}")),
+        (Some("Epic"), Some(iid)) => Some(format!("&{iid}")),
+        (Some(_), Some(iid)) => Some(format!("#{iid}")),
+        _ => None,
+    };
+    TaskReason {
+        action: action.to_string(),
+        actor: actor.map(|a| a.to_string()),
+        target_type: target_type.map(|t| t.to_string()),
+        target_reference,
+    }
+}
+
 impl GitLabSource {
-    fn query_todos<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
+    pub fn device_flow(&self) -> DeviceFlow {
+        DeviceFlow::gitlab(&self.server_url, &self.oauth_client_id)
+    }
+
+    /// Wait until no other refresh of the OAuth token is running. The refresh
+    /// token can only be redeemed once, so the lock must be held until the
+    /// new secret has been stored.
+    pub fn lock_refresh(&self) -> MutexGuard<'_, ()> {
+        self.refresh_lock.lock().expect("Lock poisoning")
+    }
+
+    /// Refresh an OAuth token that is about to expire. Returns the new secret
+    /// or `None` if the secret does not need to be refreshed. Secrets that
+    /// can't be stored are not refreshed, because the rotated refresh token
+    /// would be lost.
+    pub fn refresh_secret(&self, secret: &str, can_store: bool) -> Result<Option<String>> {
+        match OAuthToken::from_secret(secret) {
+            Some(token) if token.needs_refresh(Utc::now()) => {
+                if !can_store {
+                    bail!(
+                        "The OAuth token of {} is about to expire, but can't be refreshed \
+                         because it is provided by the environment or the secret command. \
+                         Log in again and update the provided secret.",
+                        self.name
+                    );
+                }
+                let refresh_token = token.refresh_token.unwrap_or_default();
+                let token = self.device_flow().refresh(&refresh_token)?;
+                Ok(Some(token.to_secret()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Create a task from an issue or merge request.
+    fn create_item_task(&self, item: &JsonValue, kind: TaskKind) -> Result<Task> {
+        // The reference is e.g. "group/project#12", so the project path is
+        // everything before the issue or merge request number
+        let reference = item["references"]["full"].as_str().unwrap_or_default();
+        let project = reference
+            .rsplit_once(['#', '!'])
+            .map(|(project, _)| project)
+            .unwrap_or(&self.name);
+
+        let due: Option<DateTime<Utc>> = item["due_date"]
+            .as_str()
+            .map(|due_date| NaiveDate::parse_from_str(due_date, "%Y-%m-%d"))
+            .transpose()?
+            .and_then(|due_date| due_date.and_hms_opt(0, 0, 0))
+            .map(|due_date| DateTime::from_naive_utc_and_offset(due_date, Utc));
+
+        // Identify the item by its global ID, which is stable across project
+        // renames. Todos for work items use the same ID as the issue.
+        let type_name = if kind == TaskKind::MergeRequest {
+            "MergeRequest"
+        } else {
+            "Issue"
+        };
+        let id = item["id"].as_u64().map(|id| format!("{type_name}/{id}"));
+
+        let task = Task {
+            project: project.to_string(),
+            title: item["title"].as_str().unwrap_or_default().to_string(),
+            description: item["description"].as_str().unwrap_or_default().to_string(),
+            markdown: true,
+            due,
+            created: parse_date(&item["created_at"])?,
+            id,
+            source_id: self.id,
+            source_name: self.name.clone(),
+            source_kind: Some(SourceKind::GitLab),
+            kind,
+            updated: parse_date(&item["updated_at"])?,
+            assignees: item["assignees"]
+                .members()
+                .filter_map(|a| a["username"].as_str())
+                .map(|a| a.to_string())
+                .collect(),
+            author: item["author"]["username"].as_str().map(|a| a.to_string()),
+            labels: item["labels"]
+                .members()
+                .filter_map(|l| l.as_str())
+                .map(|l| l.to_string())
+                .collect(),
+            url: item["web_url"].as_str().map(|u| u.to_string()),
+            ..Default::default()
+        };
+        Ok(task)
+    }
+
+    /// Create a task for a todo. Returns `None` for todos of closed targets
+    /// if they should be hidden.
+    fn create_todo_task(&self, todo: &JsonValue) -> Result<Option<Task>> {
+        let kind = match todo["target_type"].as_str() {
+            Some("Issue") | Some("WorkItem") => TaskKind::Issue,
+            Some("MergeRequest") => TaskKind::MergeRequest,
+            _ => TaskKind::Todo,
+        };
+        let mut task = self.create_item_task(&todo["target"], kind)?;
+
+        if let Some(project) = todo["project"]["name_with_namespace"].as_str() {
+            task.project = project.to_string();
+        }
+        if task.title.is_empty() {
+            task.title = todo["body"].as_str().unwrap_or_default().to_string();
+        }
+        task.created = parse_date(&todo["created_at"])?;
+        task.reason = Some(todo_reason(todo));
+        // GitLab keeps todos pending when their target is closed
+        task.status = match todo["target"]["state"].as_str() {
+            Some("closed") => Some("Closed".to_string()),
+            Some("merged") => Some("Merged".to_string()),
+            _ => None,
+        };
+        if task.status.is_some() && self.hide_closed_todos {
+            return Ok(None);
+        }
+
+        // Work items sometimes have a woring "target_url, but the
+        // "web_url" of the target is correct and should be prefered.
+        if task.url.is_none() {
+            task.url = todo["target_url"].as_str().map(|u| u.to_string());
+        }
+        if kind == TaskKind::Todo {
+            // Other targets like commits or epics are identified by their type
+            task.id = match (todo["target_type"].as_str(), todo["target"]["id"].as_u64()) {
+                (Some(target_type), Some(target_id)) => Some(format!("{target_type}/{target_id}")),
+                _ => None,
+            };
+        }
+        if task.id.is_none() {
+            task.id = task.url.clone();
+        }
+        Ok(Some(task))
+    }
+
+    fn call(&self, url: &str, secret: Option<&str>) -> Result<ureq::Response> {
+        let mut request = self.agent.get(url);
+        if let Some(secret) = secret {
+            request = match OAuthToken::from_secret(secret) {
+                Some(token) => {
+                    request.set("Authorization", &format!("Bearer {}", token.access_token))
+                }
+                None => request.set("PRIVATE-TOKEN", secret),
+            };
+        }
+        Ok(request.call()?)
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!("{}/{}", self.server_url.trim_end_matches('/'), path)
+    }
+
+    fn get<S>(&self, path: &str, secret: Option<S>) -> Result<JsonValue>
     where
         S: AsRef<str>,
     {
-        let mut request = self
-            .agent
-            .get(&format!("{}/todos?state=pending", self.server_url,));
-        if let Some(secret) = secret {
-            request = request.set("PRIVATE-TOKEN", secret.as_ref());
-        }
-        let response = request.call()?;
+        let response = self.call(&self.api_url(path), secret.as_ref().map(|s| s.as_ref()))?;
         let body = response.into_string()?;
-        let all_todos = json::parse(&body)?;
+        Ok(json::parse(&body)?)
+    }
 
+    /// Get all entries of a paginated list by following the `Link` header.
+    fn get_all_pages(&self, path: &str, secret: Option<&str>) -> Result<Vec<JsonValue>> {
         let mut result = Vec::default();
-
-        if let JsonValue::Array(all_todos) = all_todos {
-            for todo in all_todos {
-                let project = todo["project"]["name_with_namespace"]
-                    .as_str()
-                    .unwrap_or(&self.name);
-
-                let title = todo["body"].as_str().unwrap_or_default();
-
-                // Work items sometimes have a woring "target_url, but the
-                // "web_url" of the target is correct and should be prefered.
-                let url = if let Some(target_url) = todo["target"]["web_url"].as_str() {
-                    target_url
-                } else {
-                    todo["target_url"].as_str().unwrap_or_default()
-                };
-
-                let due: Option<DateTime<Utc>> = todo["target"]["due_date"]
-                    .as_str()
-                    .map(|due_date| NaiveDate::parse_from_str(due_date, "%Y-%m-%d"))
-                    .transpose()?
-                    .and_then(|due_date| due_date.and_hms_opt(0, 0, 0))
-                    .map(|due_date| DateTime::from_naive_utc_and_offset(due_date, Utc));
-
-                let created: Option<DateTime<Utc>> = todo["created_at"]
-                    .as_str()
-                    .map(|d| DateTime::parse_from_str(d, "%+"))
-                    .transpose()?
-                    .map(|d| d.into());
-
-                let task = Task {
-                    project: format!("{} {}", GITLAB_ICON, project),
-                    title: title.to_string(),
-                    description: url.to_string(),
-                    due,
-                    created,
-                    id: Some(url.to_string()),
-                };
-                result.push(task);
+        let mut next_url = Some(self.api_url(path));
+        let mut page = 0;
+        while let Some(url) = next_url.take() {
+            let response = self.call(&url, secret)?;
+            page += 1;
+            if page < MAX_PAGES {
+                next_url = response.header("link").and_then(next_page_url);
             }
+            let mut body = json::parse(&response.into_string()?)?;
+            result.extend(body.members_mut().map(|e| e.take()));
+        }
+        Ok(result)
+    }
+
+    fn query_todos(&self, secret: Option<&str>) -> Result<Vec<Task>> {
+        let all_todos = self.get_all_pages("todos?state=pending&per_page=100", secret)?;
+
+        let mut result = Vec::default();
+        for todo in &all_todos {
+            result.extend(self.create_todo_task(todo)?);
+        }
+        Ok(result)
+    }
+
+    fn query_items(&self, path: &str, kind: TaskKind, secret: Option<&str>) -> Result<Vec<Task>> {
+        let items = self.get_all_pages(path, secret)?;
+        let mut result = Vec::default();
+        for item in &items {
+            result.push(self.create_item_task(item, kind)?);
         }
         Ok(result)
     }
 
+    /// Query parameter for the merge requests to review by the configured
+    /// user, or the user the token belongs to. Older versions asked for the
+    /// user ID, so numeric values are treated as ID.
+    fn reviewer_parameter(&self, secret: Option<&str>) -> Result<String> {
+        let user_name = self.user_name.trim();
+        if user_name.is_empty() {
+            let user = self.get("user", secret)?;
+            let user_name = user["username"]
+                .as_str()
+                .context("Could not determine the GitLab user name")?;
+            Ok(format!("reviewer_username={user_name}"))
+        } else if user_name.parse::<u64>().is_ok() {
+            Ok(format!("reviewer_id={user_name}"))
+        } else {
+            Ok(format!("reviewer_username={user_name}"))
+        }
+    }
+
     pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
     where
         S: AsRef<str>,
     {
+        let secret = secret.as_ref().map(|s| s.as_ref());
         let mut result = Vec::default();
 
-        let todos = self.query_todos(secret)?;
-        result.extend(todos);
+        if self.todos {
+            result.extend(self.query_todos(secret)?);
+        }
+        if self.assigned_issues {
+            result.extend(self.query_items(
+                "issues?scope=assigned_to_me&state=opened&per_page=100",
+                TaskKind::Issue,
+                secret,
+            )?);
+        }
+        if self.assigned_merge_requests {
+            result.extend(self.query_items(
+                "merge_requests?scope=assigned_to_me&state=opened&per_page=100",
+                TaskKind::MergeRequest,
+                secret,
+            )?);
+        }
+        if self.review_merge_requests {
+            let reviewer = self.reviewer_parameter(secret)?;
+            result.extend(self.query_items(
+                &format!("merge_requests?scope=all&{reviewer}&state=opened&per_page=100"),
+                TaskKind::MergeRequest,
+                secret,
+            )?);
+        }
+
+        // The same issue or merge request can be in several lists, e.g. when
+        // it is assigned and there is also a todo for it
+        let mut seen = HashSet::new();
+        result.retain(|t| seen.insert(t.get_id()));
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests;
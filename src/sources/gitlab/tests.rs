@@ -0,0 +1,235 @@
+use crate::tasks::{TaskKind, TaskReason};
+
+use super::GitLabSource;
+
+#[test]
+fn parse_gitlab_todo() {
+    let json_body = r#"
+    {
+        "id": 102,
+        "project": {"name_with_namespace": "Gitlab Org / Gitlab Ce"},
+        "author": {"name": "Administrator", "username": "root"},
+        "action_name": "marked",
+        "target_type": "MergeRequest",
+        "target": {
+            "iid": 7,
+            "title": "Dolores in voluptatem tenetur praesentium omnis repellendus voluptatem quaerat.",
+            "description": "Et ea et omnis illum cupiditate.",
+            "state": "opened",
+            "updated_at": "2016-06-17T07:52:35.718Z",
+            "labels": ["backend"],
+            "assignees": [{"username": "jarrett"}],
+            "web_url": "https://gitlab.example.com/gitlab-org/gitlab-ce/-/merge_requests/7"
+        },
+        "target_url": "https://gitlab.example.com/gitlab-org/gitlab-ce/-/merge_requests/7",
+        "body": "Dolores in voluptatem tenetur praesentium omnis repellendus voluptatem quaerat.",
+        "state": "pending",
+        "created_at": "2016-06-17T07:52:35.225Z"
+    }
+    "#;
+    let todo = json::parse(json_body).unwrap();
+    let source = GitLabSource::default();
+
+    let task = source.create_todo_task(&todo).unwrap().unwrap();
+    assert_eq!("Gitlab Org / Gitlab Ce", task.project);
+    assert_eq!("Et ea et omnis illum cupiditate.", task.description);
+    assert_eq!(
+        Some("https://gitlab.example.com/gitlab-org/gitlab-ce/-/merge_requests/7"),
+        task.url.as_deref()
+    );
+    assert_eq!(TaskKind::MergeRequest, task.kind);
+    assert_eq!(vec!["backend"], task.labels);
+    assert_eq!(vec!["jarrett"], task.assignees);
+    assert!(task.updated.is_some());
+    assert_eq!(
+        Some(TaskReason {
+            action: "marked".to_string(),
+            actor: Some("Administrator".to_string()),
+            target_type: Some("MergeRequest".to_string()),
+            target_reference: Some("!7".to_string()),
+        }),
+        task.reason
+    );
+    assert_eq!(None, task.status);
+}
+
+#[test]
+fn gitlab_todo_reason() {
+    let review = json::parse(
+        r#"
+    {
+        "author": {"name": "Alice", "username": "alice"},
+        "action_name": "review_requested",
+        "target_type": "MergeRequest",
+        "target": {"id": 1, "iid": 123, "title": "Add feature", "state": "merged"},
+        "body": "Add feature"
+    }
+    "#,
+    )
+    .unwrap();
+    let mut source = GitLabSource::default();
+    // Todos of merged merge requests are hidden by default
+    assert!(source.create_todo_task(&review).unwrap().is_none());
+    source.hide_closed_todos = false;
+    let task = source.create_todo_task(&review).unwrap().unwrap();
+    let reason = task.reason.unwrap();
+    assert_eq!("review_requested", reason.action);
+    assert_eq!(Some("Alice"), reason.actor.as_deref());
+    assert_eq!(Some("MergeRequest"), reason.target_type.as_deref());
+    assert_eq!(Some("!123"), reason.target_reference.as_deref());
+    assert_eq!(Some("Merged"), task.status.as_deref());
+
+    let pipeline = json::parse(
+        r#"
+    {
+        "author": {"name": "Alice", "username": "alice"},
+        "action_name": "build_failed",
+        "target_type": "MergeRequest",
+        "target": {"id": 1, "iid": 123, "title": "Add feature", "state": "opened"}
+    }
+    "#,
+    )
+    .unwrap();
+    let task = source.create_todo_task(&pipeline).unwrap().unwrap();
+    // The pipeline has not been failed by a person
+    assert_eq!(None, task.reason.unwrap().actor);
+
+    let unknown = json::parse(
+        r#"
+    {
+        "author": {"username": "bob"},
+        "action_name": "okr_checkin_requested",
+        "target_type": "Issue",
+        "target": {"id": 2, "iid": 5, "title": "Objective"}
+    }
+    "#,
+    )
+    .unwrap();
+    let task = source.create_todo_task(&unknown).unwrap().unwrap();
+    let reason = task.reason.unwrap();
+    assert_eq!("okr_checkin_requested", reason.action);
+    assert_eq!(Some("bob"), reason.actor.as_deref());
+    assert_eq!(Some("#5"), reason.target_reference.as_deref());
+}
+
+#[test]
+fn parse_gitlab_merge_request() {
+    let json_body = r#"
+    {
+        "id": 1,
+        "iid": 1,
+        "project_id": 3,
+        "title": "test1",
+        "description": "fixed login page css paddings",
+        "state": "opened",
+        "created_at": "2017-04-29T08:46:00Z",
+        "updated_at": "2017-04-29T08:46:00Z",
+        "author": {"username": "admin"},
+        "assignees": [{"username": "admin"}],
+        "reviewers": [{"username": "jarrett"}],
+        "labels": ["Community contribution"],
+        "references": {"short": "!1", "relative": "!1", "full": "my-group/my-project!1"},
+        "web_url": "http://gitlab.example.com/my-group/my-project/merge_requests/1"
+    }
+    "#;
+    let merge_request = json::parse(json_body).unwrap();
+    let source = GitLabSource::default();
+
+    let task = source
+        .create_item_task(&merge_request, TaskKind::MergeRequest)
+        .unwrap();
+    assert_eq!("my-group/my-project", task.project);
+    assert_eq!("test1", task.title);
+    assert_eq!(Some("MergeRequest/1"), task.id.as_deref());
+    assert_eq!(Some("admin"), task.author.as_deref());
+    assert_eq!(vec!["admin"], task.assignees);
+    assert!(task.created.is_some());
+}
+
+#[test]
+fn gitlab_todo_and_item_have_same_id() {
+    let todo = json::parse(
+        r#"
+    {
+        "project": {"name_with_namespace": "My Group / My Project"},
+        "target_type": "WorkItem",
+        "target": {"id": 42, "iid": 3, "title": "Fix it", "web_url": "https://gitlab.example.com/g/p/-/work_items/3"},
+        "body": "Fix it",
+        "created_at": "2016-06-17T07:52:35.225Z"
+    }
+    "#,
+    )
+    .unwrap();
+    let issue = json::parse(
+        r#"
+    {
+        "id": 42,
+        "iid": 3,
+        "title": "Fix it",
+        "references": {"full": "g/p#3"},
+        "web_url": "https://gitlab.example.com/g/p/-/issues/3"
+    }
+    "#,
+    )
+    .unwrap();
+    let source = GitLabSource::default();
+
+    let from_todo = source.create_todo_task(&todo).unwrap().unwrap();
+    let from_issue = source.create_item_task(&issue, TaskKind::Issue).unwrap();
+    assert_eq!(from_todo.get_id(), from_issue.get_id());
+    assert_eq!("My Group / My Project", from_todo.project);
+    assert_eq!("g/p", from_issue.project);
+}
+
+#[test]
+fn gitlab_items_are_paginated() {
+    let mut server = mockito::Server::new();
+    let source = GitLabSource {
+        server_url: format!("{}/api/v4/", server.url()),
+        ..Default::default()
+    };
+    server
+        .mock("GET", "/api/v4/issues")
+        .match_query(mockito::Matcher::UrlEncoded("page".into(), "2".into()))
+        .with_body(r#"[{"id": 2, "title": "Second"}]"#)
+        .create();
+    server
+        .mock("GET", "/api/v4/issues")
+        .match_query(mockito::Matcher::Missing)
+        .with_header(
+            "Link",
+            &format!(
+                r#"<{}/api/v4/issues?page=2&per_page=1>; rel="next", <{0}/api/v4/issues?page=2&per_page=1>; rel="last""#,
+                server.url()
+            ),
+        )
+        .with_body(r#"[{"id": 1, "title": "First"}]"#)
+        .create();
+
+    let tasks = source.query_items("issues", TaskKind::Issue, None).unwrap();
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(vec!["First", "Second"], titles);
+}
+
+#[test]
+fn gitlab_reviewer_parameter() {
+    let mut source = GitLabSource {
+        user_name: "jarrett".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        "reviewer_username=jarrett",
+        source.reviewer_parameter(None).unwrap()
+    );
+    // Older versions asked for the user ID
+    source.user_name = " 42 ".to_string();
+    assert_eq!("reviewer_id=42", source.reviewer_parameter(None).unwrap());
+}
+
+#[test]
+fn gitlab_token_refresh_lock_is_shared_by_clones() {
+    let source = GitLabSource::default();
+    let cloned_source = source.clone();
+    let _guard = source.lock_refresh();
+    assert!(cloned_source.refresh_lock.try_lock().is_err());
+}
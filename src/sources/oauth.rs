@@ -0,0 +1,254 @@
+use std::{thread, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use json::{object, JsonValue};
+use ureq::Agent;
+
+/// Tokens are refreshed this long before they actually expire.
+const EXPIRY_MARGIN: TimeDelta = TimeDelta::minutes(1);
+
+/// Token obtained with OAuth. It is stored as the secret of a source, in a
+/// JSON representation that can be distinguished from plain personal access
+/// tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl OAuthToken {
+    /// Parse a secret, which is `None` if the secret is a plain token.
+    pub fn from_secret(secret: &str) -> Option<Self> {
+        let value = json::parse(secret).ok()?;
+        Some(OAuthToken {
+            access_token: value["oauth_access_token"].as_str()?.to_string(),
+            refresh_token: value["refresh_token"].as_str().map(|t| t.to_string()),
+            expires_at: value["expires_at"]
+                .as_str()
+                .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+                .map(|e| e.into()),
+        })
+    }
+
+    pub fn to_secret(&self) -> String {
+        let mut value = object! {
+            oauth_access_token: self.access_token.as_str(),
+        };
+        if let Some(refresh_token) = &self.refresh_token {
+            value["refresh_token"] = refresh_token.as_str().into();
+        }
+        if let Some(expires_at) = self.expires_at {
+            value["expires_at"] = expires_at.to_rfc3339().into();
+        }
+        value.dump()
+    }
+
+    pub fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        self.refresh_token.is_some()
+            && self
+                .expires_at
+                .is_some_and(|expires_at| expires_at - EXPIRY_MARGIN <= now)
+    }
+
+    fn from_response(response: &JsonValue, now: DateTime<Utc>) -> Result<Self> {
+        Ok(OAuthToken {
+            access_token: response["access_token"]
+                .as_str()
+                .context("Missing 'access_token' in token response")?
+                .to_string(),
+            refresh_token: response["refresh_token"].as_str().map(|t| t.to_string()),
+            expires_at: response["expires_in"]
+                .as_i64()
+                .map(|seconds| now + TimeDelta::seconds(seconds)),
+        })
+    }
+}
+
+/// The token to use for API requests, either from an OAuth token or the
+/// secret itself, if it is a personal access token.
+pub fn access_token(secret: &str) -> String {
+    OAuthToken::from_secret(secret)
+        .map(|t| t.access_token)
+        .unwrap_or_else(|| secret.to_string())
+}
+
+/// Information the user needs to authorize the device.
+#[derive(Debug, Clone)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_at: DateTime<Utc>,
+    pub interval: Duration,
+}
+
+/// Result of a single request for the token.
+#[derive(Debug)]
+pub enum PollResult {
+    Pending,
+    SlowDown,
+    Token(OAuthToken),
+}
+
+/// OAuth 2.0 device authorization grant (RFC 8628).
+pub struct DeviceFlow {
+    agent: Agent,
+    device_code_url: String,
+    token_url: String,
+    client_id: String,
+    scope: String,
+}
+
+impl DeviceFlow {
+    /// Device flow for GitHub. The `api_url` is the URL of the REST API, e.g.
+    /// `https://api.github.com` or `https://github.example.com/api/v3` for
+    /// GitHub Enterprise.
+    pub fn github(api_url: &str, client_id: &str) -> Self {
+        let api_url = api_url.trim_end_matches('/');
+        let web_url = if api_url == "https://api.github.com" {
+            "https://github.com"
+        } else {
+            api_url.trim_end_matches("/api/v3")
+        };
+        DeviceFlow {
+            agent: Agent::new(),
+            device_code_url: format!("{web_url}/login/device/code"),
+            token_url: format!("{web_url}/login/oauth/access_token"),
+            client_id: client_id.to_string(),
+            scope: "repo".to_string(),
+        }
+    }
+
+    /// Device flow for GitLab. The `api_url` is the URL of the REST API, e.g.
+    /// `https://gitlab.com/api/v4/`.
+    pub fn gitlab(api_url: &str, client_id: &str) -> Self {
+        let web_url = api_url.trim_end_matches('/').trim_end_matches("/api/v4");
+        DeviceFlow {
+            agent: Agent::new(),
+            device_code_url: format!("{web_url}/oauth/authorize_device"),
+            token_url: format!("{web_url}/oauth/token"),
+            client_id: client_id.to_string(),
+            scope: "read_api".to_string(),
+        }
+    }
+
+    pub fn has_client_id(&self) -> bool {
+        !self.client_id.trim().is_empty()
+    }
+
+    fn post(&self, url: &str, form: &[(&str, &str)]) -> Result<JsonValue> {
+        let response = self
+            .agent
+            .post(url)
+            .set("Accept", "application/json")
+            .send_form(form);
+        // Errors like "authorization_pending" are reported with status 400
+        let body = match response {
+            Ok(response) => response.into_string()?,
+            Err(ureq::Error::Status(_, response)) => response.into_string()?,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(json::parse(&body)?)
+    }
+
+    /// Request a device and user code.
+    pub fn start(&self) -> Result<DeviceAuthorization> {
+        let response = self.post(
+            &self.device_code_url,
+            &[("client_id", &self.client_id), ("scope", &self.scope)],
+        )?;
+        if let Some(error) = response["error"].as_str() {
+            bail!("Device authorization failed: {error}");
+        }
+        let field = |name: &str| -> Result<String> {
+            Ok(response[name]
+                .as_str()
+                .with_context(|| format!("Missing '{name}' in device authorization"))?
+                .to_string())
+        };
+        Ok(DeviceAuthorization {
+            device_code: field("device_code")?,
+            user_code: field("user_code")?,
+            verification_uri: field("verification_uri")?,
+            expires_at: Utc::now()
+                + TimeDelta::seconds(response["expires_in"].as_i64().unwrap_or(900)),
+            interval: Duration::from_secs(response["interval"].as_u64().unwrap_or(5)),
+        })
+    }
+
+    /// Ask once whether the user has authorized the device.
+    pub fn poll(&self, authorization: &DeviceAuthorization) -> Result<PollResult> {
+        let response = self.post(
+            &self.token_url,
+            &[
+                ("client_id", &self.client_id),
+                ("device_code", &authorization.device_code),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ],
+        )?;
+        match response["error"].as_str() {
+            None => Ok(PollResult::Token(OAuthToken::from_response(
+                &response,
+                Utc::now(),
+            )?)),
+            Some("authorization_pending") => Ok(PollResult::Pending),
+            Some("slow_down") => Ok(PollResult::SlowDown),
+            Some("access_denied") => bail!("The authorization has been denied"),
+            Some("expired_token") => bail!("The user code has expired"),
+            Some(error) => bail!("Authorization failed: {error}"),
+        }
+    }
+
+    /// Poll until the user has authorized the device, the code has expired or
+    /// `is_cancelled` returns true.
+    pub fn wait_for_token<F>(
+        &self,
+        authorization: &DeviceAuthorization,
+        is_cancelled: F,
+    ) -> Result<OAuthToken>
+    where
+        F: Fn() -> bool,
+    {
+        let mut interval = authorization.interval;
+        loop {
+            if is_cancelled() {
+                bail!("Login has been cancelled");
+            }
+            if Utc::now() > authorization.expires_at {
+                bail!("The user code has expired");
+            }
+            thread::sleep(interval);
+            match self.poll(authorization)? {
+                PollResult::Pending => {}
+                PollResult::SlowDown => interval += Duration::from_secs(5),
+                PollResult::Token(token) => return Ok(token),
+            }
+        }
+    }
+
+    /// Get a new access token with the refresh token.
+    pub fn refresh(&self, refresh_token: &str) -> Result<OAuthToken> {
+        let response = self.post(
+            &self.token_url,
+            &[
+                ("client_id", &self.client_id),
+                ("refresh_token", refresh_token),
+                ("grant_type", "refresh_token"),
+            ],
+        )?;
+        if let Some(error) = response["error"].as_str() {
+            bail!("Refreshing the OAuth token failed: {error}");
+        }
+        let mut token = OAuthToken::from_response(&response, Utc::now())?;
+        // Keep the old refresh token if the server does not rotate it
+        if token.refresh_token.is_none() {
+            token.refresh_token = Some(refresh_token.to_string());
+        }
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests;
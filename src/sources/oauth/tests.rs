@@ -0,0 +1,139 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use mockito::Matcher;
+
+use super::{access_token, DeviceFlow, OAuthToken, PollResult};
+
+#[test]
+fn test_token_secret_roundtrip() {
+    let token = OAuthToken {
+        access_token: "access".to_string(),
+        refresh_token: Some("refresh".to_string()),
+        expires_at: Some(Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()),
+    };
+    let secret = token.to_secret();
+    assert_eq!(Some(token), OAuthToken::from_secret(&secret));
+    assert_eq!("access", access_token(&secret));
+
+    // Personal access tokens are used as they are
+    assert_eq!(None, OAuthToken::from_secret("glpat-1234"));
+    assert_eq!("glpat-1234", access_token("glpat-1234"));
+}
+
+#[test]
+fn test_needs_refresh() {
+    let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    let token = OAuthToken {
+        access_token: "access".to_string(),
+        refresh_token: Some("refresh".to_string()),
+        expires_at: Some(now + TimeDelta::seconds(30)),
+    };
+    assert!(token.needs_refresh(now));
+    assert!(!token.needs_refresh(now - TimeDelta::hours(1)));
+    let without_refresh_token = OAuthToken {
+        refresh_token: None,
+        ..token
+    };
+    assert!(!without_refresh_token.needs_refresh(now));
+}
+
+#[test]
+fn test_github_device_flow() {
+    let mut server = mockito::Server::new();
+    let device_code = server
+        .mock("POST", "/login/device/code")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("client_id".into(), "client".into()),
+            Matcher::UrlEncoded("scope".into(), "repo".into()),
+        ]))
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{"device_code": "device", "user_code": "ABCD-1234",
+                "verification_uri": "https://github.com/login/device",
+                "expires_in": 900, "interval": 0}"#,
+        )
+        .create();
+    let pending = server
+        .mock("POST", "/login/oauth/access_token")
+        .match_body(Matcher::UrlEncoded("device_code".into(), "device".into()))
+        .with_status(400)
+        .with_body(r#"{"error": "authorization_pending"}"#)
+        .expect(2)
+        .create();
+    let token = server
+        .mock("POST", "/login/oauth/access_token")
+        .match_body(Matcher::UrlEncoded(
+            "grant_type".into(),
+            "urn:ietf:params:oauth:grant-type:device_code".into(),
+        ))
+        .with_body(r#"{"access_token": "gho_token", "token_type": "bearer", "scope": "repo"}"#)
+        .create();
+
+    let flow = DeviceFlow::github(&format!("{}/api/v3", server.url()), "client");
+    let authorization = flow.start().unwrap();
+    assert_eq!("ABCD-1234", authorization.user_code);
+    assert_eq!(
+        "https://github.com/login/device",
+        authorization.verification_uri
+    );
+
+    assert!(matches!(
+        flow.poll(&authorization).unwrap(),
+        PollResult::Pending
+    ));
+    let result = flow.wait_for_token(&authorization, || false).unwrap();
+    assert_eq!("gho_token", result.access_token);
+    assert_eq!(None, result.refresh_token);
+
+    device_code.assert();
+    pending.assert();
+    token.assert();
+}
+
+#[test]
+fn test_device_flow_denied() {
+    let mut server = mockito::Server::new();
+    server
+        .mock("POST", "/oauth/authorize_device")
+        .with_body(
+            r#"{"device_code": "device", "user_code": "ABCD",
+                "verification_uri": "https://gitlab.com/oauth/device",
+                "expires_in": 300, "interval": 0}"#,
+        )
+        .create();
+    server
+        .mock("POST", "/oauth/token")
+        .with_status(400)
+        .with_body(r#"{"error": "access_denied"}"#)
+        .create();
+
+    let flow = DeviceFlow::gitlab(&format!("{}/api/v4/", server.url()), "client");
+    let authorization = flow.start().unwrap();
+    assert!(flow.wait_for_token(&authorization, || false).is_err());
+    // A cancelled login does not poll at all
+    assert!(flow.wait_for_token(&authorization, || true).is_err());
+}
+
+#[test]
+fn test_gitlab_refresh_token() {
+    let mut server = mockito::Server::new();
+    let refresh = server
+        .mock("POST", "/oauth/token")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("grant_type".into(), "refresh_token".into()),
+            Matcher::UrlEncoded("refresh_token".into(), "old-refresh".into()),
+            Matcher::UrlEncoded("client_id".into(), "client".into()),
+        ]))
+        .with_body(
+            r#"{"access_token": "new-access", "refresh_token": "new-refresh",
+                "expires_in": 7200, "token_type": "Bearer"}"#,
+        )
+        .create();
+
+    let flow = DeviceFlow::gitlab(&format!("{}/api/v4/", server.url()), "client");
+    let before = Utc::now();
+    let token = flow.refresh("old-refresh").unwrap();
+    assert_eq!("new-access", token.access_token);
+    assert_eq!(Some("new-refresh".to_string()), token.refresh_token);
+    assert!(token.expires_at.unwrap() >= before + TimeDelta::seconds(7200));
+    refresh.assert();
+}
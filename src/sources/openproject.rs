@@ -1,35 +1,63 @@
+use std::collections::{BTreeMap, HashMap};
+
 use anyhow::{anyhow, Context, Result};
 use base64::prelude::*;
 use chrono::{DateTime, Local, NaiveDate, Utc};
-use json::{array, JsonValue};
+use json::{array, object, JsonValue};
 use serde::{Deserialize, Serialize};
 use ureq::Agent;
+use url::Url;
+use uuid::Uuid;
+
+use crate::tasks::{Task, TaskKind};
 
-use crate::tasks::Task;
+use super::{SourceKind, MAX_PAGES};
 
-use super::OPENPROJECT_ICON;
+/// Number of work packages requested per page. Servers with a lower maximum
+/// page size use their maximum instead.
+const PAGE_SIZE: &str = "100";
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct OpenProjectSource {
     #[serde(skip)]
     agent: Agent,
+    /// Stable identifier of this source, which does not change when the
+    /// source is renamed. Sources of older versions have no ID, which is
+    /// read as nil ID and replaced by [`TaskSource::migrate_legacy_settings`].
+    #[serde(default)]
+    pub id: Uuid,
     pub name: String,
     pub server_url: String,
+    /// Work package types (ID and name) to include. All types are included
+    /// if empty.
+    pub types: BTreeMap<u64, String>,
+    /// ID of a saved query to run instead of listing the assigned work
+    /// packages.
+    pub query_id: Option<u64>,
 }
 
 impl Default for OpenProjectSource {
     fn default() -> Self {
         Self {
             agent: Agent::new(),
+            id: Uuid::new_v4(),
             name: "OpenProject".to_string(),
             server_url: "https://community.openproject.org".to_string(),
+            types: BTreeMap::from([(1, "Task".to_string())]),
+            query_id: None,
         }
     }
 }
 
 impl OpenProjectSource {
-    fn create_task(&self, work_package: &JsonValue) -> Result<Option<Task>> {
+    /// Create a task for a work package. The priorities map the IDs of the
+    /// priorities to the priority of the task.
+    fn create_task(
+        &self,
+        work_package: &JsonValue,
+        priorities: &HashMap<u64, u8>,
+    ) -> Result<Option<Task>> {
         if let JsonValue::Object(work_package) = work_package {
             let title = work_package["subject"].as_str().unwrap_or("<unknown>");
             let id = work_package["id"]
@@ -37,7 +65,9 @@ impl OpenProjectSource {
                 .context("'id' field in response is not an integer")?;
             let url = format!("{}/work_packages/{id}/activity", self.server_url);
 
-            let project = work_package["_links"]["project"]["title"].to_string();
+            let project = work_package["_links"]["project"]["title"]
+                .as_str()
+                .unwrap_or(&self.name);
 
             let created = if let Some(c) = work_package["createdAt"].as_str() {
                 let created_utc: DateTime<Utc> = DateTime::parse_from_rfc3339(c)?.into();
@@ -68,13 +98,46 @@ impl OpenProjectSource {
                     .and_then(|due_date| due_date.and_hms_opt(0, 0, 0))
                     .map(|due_date| DateTime::from_naive_utc_and_offset(due_date, Utc));
 
+                let updated = if let Some(u) = work_package["updatedAt"].as_str() {
+                    let updated_utc: DateTime<Utc> = DateTime::parse_from_rfc3339(u)?.into();
+                    Some(updated_utc)
+                } else {
+                    None
+                };
+
                 let t = Task {
-                    project: format!("{} {}", OPENPROJECT_ICON, project),
+                    project: project.to_string(),
                     title: title.to_string(),
-                    description: url,
+                    description: work_package["description"]["raw"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    markdown: work_package["description"]["format"] == "markdown",
                     due,
                     created,
                     id: Some(id.to_string()),
+                    source_id: self.id,
+                    source_name: self.name.clone(),
+                    source_kind: Some(SourceKind::OpenProject),
+                    kind: TaskKind::WorkPackage,
+                    start,
+                    updated,
+                    assignees: work_package["_links"]["assignee"]["title"]
+                        .as_str()
+                        .map(|a| vec![a.to_string()])
+                        .unwrap_or_default(),
+                    priority: work_package["_links"]["priority"]["href"]
+                        .as_str()
+                        .and_then(|href| href.rsplit('/').next()?.parse().ok())
+                        .and_then(|id: u64| priorities.get(&id).copied()),
+                    percent_complete: work_package["percentageDone"]
+                        .as_u64()
+                        .map(|p| p.min(100) as u8),
+                    url: Some(url),
+                    status: work_package["_links"]["status"]["title"]
+                        .as_str()
+                        .map(|s| s.to_string()),
+                    ..Default::default()
                 };
                 Ok(Some(t))
             } else {
@@ -85,82 +148,185 @@ impl OpenProjectSource {
         }
     }
 
-    pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
-    where
-        S: AsRef<str>,
-    {
-        let mut result = Vec::default();
-
-        let basic_auth = secret.map(|secret| format!("apikey:{}", secret.as_ref()));
-
-        // Query all statuses that count as "closed".
-        let mut request = self
-            .agent
-            .get(&format!("{}/api/v3/statuses", self.server_url));
-        if let Some(basic_auth) = &basic_auth {
+    fn call(&self, mut request: ureq::Request, basic_auth: Option<&str>) -> Result<JsonValue> {
+        if let Some(basic_auth) = basic_auth {
             request = request.set(
                 "Authorization",
-                &format!("Basic {}", &BASE64_STANDARD.encode(basic_auth.clone())),
+                &format!("Basic {}", &BASE64_STANDARD.encode(basic_auth)),
             )
         }
         let response = request.call()?;
         let body = response.into_string()?;
-        let closed_statuses =
-            if let JsonValue::Array(elements) = &json::parse(&body)?["_embedded"]["elements"] {
-                elements
-                    .iter()
-                    .filter(|e| e["isClosed"].as_bool().unwrap_or(false))
-                    .filter_map(|e| e["id"].as_usize())
-                    .collect()
-            } else {
-                Vec::default()
-            };
+        Ok(json::parse(&body)?)
+    }
 
-        // Get the user ID for the provided acccess token
-        let mut request = self
+    fn get(&self, path: &str, basic_auth: Option<&str>) -> Result<JsonValue> {
+        let request = self
             .agent
-            .get(&format!("{}/api/v3/users/me", self.server_url));
-        if let Some(basic_auth) = &basic_auth {
-            request = request.set(
-                "Authorization",
-                &format!("Basic {}", &BASE64_STANDARD.encode(basic_auth.clone())),
-            )
-        }
-        let response = request.call()?;
-        let body = response.into_string()?;
-
-        let user_id = json::parse(&body)?["id"].as_usize().unwrap_or(0);
-        // Filter by work packages that are assigned to the use and are not closed
-        let filter_param = array! [
-            {"assignee": {"operator": "=", "values": [user_id]}},
-            {"type": {"operator": "=", "values": [1]}},
-            {"status": {"operator": "!", "values": closed_statuses.clone()}}
-        ];
+            .get(&format!("{}/api/v3/{path}", self.server_url));
+        self.call(request, basic_auth)
+    }
 
+    /// Get all work packages of a collection or of the results of a saved
+    /// query, following the links to the next pages.
+    fn get_all_work_packages(
+        &self,
+        path: &str,
+        filters: Option<&JsonValue>,
+        basic_auth: Option<&str>,
+    ) -> Result<Vec<JsonValue>> {
         let mut request = self
             .agent
-            .get(&format!("{}/api/v3/work_packages", self.server_url))
-            .query("filters", &filter_param.to_string());
-        if let Some(basic_auth) = &basic_auth {
-            request = request.set(
-                "Authorization",
-                &format!("Basic {}", &BASE64_STANDARD.encode(basic_auth.clone())),
-            )
+            .get(&format!("{}/api/v3/{path}", self.server_url))
+            .query("pageSize", PAGE_SIZE);
+        if let Some(filters) = filters {
+            request = request.query("filters", &filters.to_string());
         }
-        let response = request.call()?;
-        let body = response.into_string()?;
-        let work_package_collection = json::parse(&body)?;
-
-        if let JsonValue::Array(elements) = &work_package_collection["_embedded"]["elements"] {
-            for e in elements {
-                if let Some(task) = self.create_task(e)? {
-                    result.push(task);
+        let mut result = Vec::default();
+        let mut next_request = Some(request);
+        let mut page = 0;
+        while let Some(request) = next_request.take() {
+            let mut collection = self.call(request, basic_auth)?;
+            // Saved queries embed the collection of their results
+            if collection["_type"] == "Query" {
+                collection = collection["_embedded"]["results"].take();
+            }
+            page += 1;
+            if page < MAX_PAGES {
+                if let Some(href) = collection["_links"]["nextByOffset"]["href"].as_str() {
+                    // The link is relative to the server
+                    let url = Url::parse(&self.server_url)?.join(href)?;
+                    next_request = Some(self.agent.get(url.as_str()));
                 }
             }
+            result.extend(
+                collection["_embedded"]["elements"]
+                    .members_mut()
+                    .map(|e| e.take()),
+            );
         }
+        Ok(result)
+    }
+
+    /// Get all work package types of the server by their ID.
+    pub fn discover_types<S>(&self, secret: Option<S>) -> Result<BTreeMap<u64, String>>
+    where
+        S: AsRef<str>,
+    {
+        let basic_auth = secret.map(|secret| format!("apikey:{}", secret.as_ref()));
+        let types = self.get("types", basic_auth.as_deref())?;
+        Ok(parse_types(&types))
+    }
 
+    fn create_tasks(
+        &self,
+        work_packages: &[JsonValue],
+        priorities: &HashMap<u64, u8>,
+    ) -> Result<Vec<Task>> {
+        let mut result = Vec::default();
+        for e in work_packages {
+            if let Some(task) = self.create_task(e, priorities)? {
+                result.push(task);
+            }
+        }
         Ok(result)
     }
+
+    pub fn query_tasks<S>(&self, secret: Option<S>) -> Result<Vec<Task>>
+    where
+        S: AsRef<str>,
+    {
+        let basic_auth = secret.map(|secret| format!("apikey:{}", secret.as_ref()));
+        let basic_auth = basic_auth.as_deref();
+        let priorities = parse_priorities(&self.get("priorities", basic_auth)?);
+
+        if let Some(query_id) = self.query_id {
+            // The saved query defines which work packages are included
+            let work_packages =
+                self.get_all_work_packages(&format!("queries/{query_id}"), None, basic_auth)?;
+            return self.create_tasks(&work_packages, &priorities);
+        }
+
+        // Query all statuses that count as "closed".
+        let closed_statuses = if let JsonValue::Array(elements) =
+            &self.get("statuses", basic_auth)?["_embedded"]["elements"]
+        {
+            elements
+                .iter()
+                .filter(|e| e["isClosed"].as_bool().unwrap_or(false))
+                .filter_map(|e| e["id"].as_usize())
+                .collect()
+        } else {
+            Vec::default()
+        };
+
+        // Get the user ID for the provided acccess token
+        let user_id = self.get("users/me", basic_auth)?["id"]
+            .as_usize()
+            .unwrap_or(0);
+        // Filter by work packages that are assigned to the use and are not closed
+        let mut filter_param = array! [
+            {"assignee": {"operator": "=", "values": [user_id]}},
+            {"status": {"operator": "!", "values": closed_statuses.clone()}}
+        ];
+        if !self.types.is_empty() {
+            let type_ids: Vec<u64> = self.types.keys().copied().collect();
+            filter_param.push(object! {"type": {"operator": "=", "values": type_ids}})?;
+        }
+
+        let work_packages =
+            self.get_all_work_packages("work_packages", Some(&filter_param), basic_auth)?;
+        self.create_tasks(&work_packages, &priorities)
+    }
+}
+
+/// Map the priorities of the server to the iCalendar priorities used by tasks,
+/// by their position relative to the default priority. Priorities with a
+/// higher position are more important. The default priority is not shown.
+fn parse_priorities(priorities: &JsonValue) -> HashMap<u64, u8> {
+    let positions: Vec<(u64, u64, bool)> = priorities["_embedded"]["elements"]
+        .members()
+        .filter_map(|p| {
+            Some((
+                p["id"].as_u64()?,
+                p["position"].as_u64()?,
+                p["isDefault"].as_bool().unwrap_or(false),
+            ))
+        })
+        .collect();
+    let (Some(lowest), Some(highest)) = (
+        positions.iter().map(|p| p.1).min(),
+        positions.iter().map(|p| p.1).max(),
+    ) else {
+        return HashMap::default();
+    };
+    let default = positions
+        .iter()
+        .find(|p| p.2)
+        .map(|p| p.1)
+        .unwrap_or((lowest + highest) / 2);
+
+    positions
+        .iter()
+        .filter_map(|&(id, position, _)| {
+            let priority = if position > default {
+                1 + (highest - position) * 4 / (highest - default)
+            } else if position < default {
+                9 - (position - lowest) * 4 / (default - lowest)
+            } else {
+                return None;
+            };
+            Some((id, priority as u8))
+        })
+        .collect()
+}
+
+/// Parse the collection of work package types.
+fn parse_types(types: &JsonValue) -> BTreeMap<u64, String> {
+    types["_embedded"]["elements"]
+        .members()
+        .filter_map(|t| Some((t["id"].as_u64()?, t["name"].as_str()?.to_string())))
+        .collect()
 }
 
 #[cfg(test)]
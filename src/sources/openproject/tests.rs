@@ -1,8 +1,11 @@
-use crate::sources::OPENPROJECT_ICON;
+use std::collections::HashMap;
 
-use super::OpenProjectSource;
+use crate::tasks::TaskKind;
+
+use super::{parse_priorities, parse_types, OpenProjectSource};
 
 #[test]
+#[allow(clippy::needless_borrow, clippy::bool_assert_comparison)]
 fn parse_open_project_task() {
     let json_body = r#"
     {
@@ -29,7 +32,7 @@ fn parse_open_project_task() {
         "derivedRemainingTime": null,
         "duration": null,
         "ignoreNonWorkingDays": false,
-        "percentageDone": null,
+        "percentageDone": 30,
         "derivedPercentageDone": null,
         "createdAt": "2024-05-10T05:36:41.859Z",
         "updatedAt": "2024-05-30T12:18:44.591Z",
@@ -37,7 +40,10 @@ fn parse_open_project_task() {
         "customField4": null,
         "customField3": null,
         "_links": {
-            "project": {"href": "/api/v3/projects/1337", "title": "Test project"}
+            "project": {"href": "/api/v3/projects/1337", "title": "Test project"},
+            "assignee": {"href": "/api/v3/users/1", "title": "Jane Doe"},
+            "priority": {"href": "/api/v3/priorities/10", "title": "High"},
+            "status": {"href": "/api/v3/statuses/7", "title": "In progress"}
         }
       }
     "#;
@@ -46,14 +52,133 @@ fn parse_open_project_task() {
 
     let source = OpenProjectSource::default();
 
-    let task = source.create_task(&work_package).unwrap();
+    let priorities = HashMap::from([(10, 3)]);
+    let task = source.create_task(&work_package, &priorities).unwrap();
     assert_eq!(true, task.is_some());
     let task = task.unwrap();
 
     assert_eq!("Test title", task.title);
     assert_eq!(
-        "https://community.openproject.org/work_packages/33013/activity",
-        task.description
+        Some("https://community.openproject.org/work_packages/33013/activity"),
+        task.url.as_deref()
+    );
+    assert_eq!("Test project", task.project);
+    assert_eq!(TaskKind::WorkPackage, task.kind);
+    assert_eq!(vec!["Jane Doe"], task.assignees);
+    assert!(task.start.is_some());
+    assert!(task.updated.is_some());
+    assert_eq!("This is a *description*.", task.description);
+    assert!(task.markdown);
+    assert_eq!(Some(3), task.priority);
+    assert_eq!(Some(30), task.percent_complete);
+    assert_eq!(Some("In progress"), task.status.as_deref());
+}
+
+#[test]
+fn parse_open_project_task_without_links() {
+    let work_package = json::parse(r#"{"id": 1, "subject": "Minimal", "_links": {}}"#).unwrap();
+    let source = OpenProjectSource::default();
+
+    let task = source
+        .create_task(&work_package, &HashMap::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!("OpenProject", task.project);
+    assert_eq!("", task.description);
+    assert_eq!(None, task.priority);
+    assert_eq!(None, task.status);
+}
+
+#[test]
+fn parse_open_project_types() {
+    let json_body = r#"
+    {
+        "_type": "Collection",
+        "total": 3,
+        "count": 3,
+        "_embedded": {
+            "elements": [
+                {"_type": "Type", "id": 1, "name": "Task", "isMilestone": false},
+                {"_type": "Type", "id": 4, "name": "Feature", "isMilestone": false},
+                {"_type": "Type", "id": 7, "name": "Bug", "isMilestone": false}
+            ]
+        }
+    }
+    "#;
+    let types = parse_types(&json::parse(json_body).unwrap());
+    assert_eq!(
+        vec![(1, "Task"), (4, "Feature"), (7, "Bug")],
+        types
+            .iter()
+            .map(|(id, name)| (*id, name.as_str()))
+            .collect::<Vec<_>>()
     );
-    assert_eq!(format!("{} Test project", OPENPROJECT_ICON), task.project);
+}
+
+#[test]
+fn parse_open_project_priorities() {
+    let json_body = r#"
+    {
+        "_type": "Collection",
+        "_embedded": {
+            "elements": [
+                {"_type": "Priority", "id": 7, "name": "Niedrig", "position": 1, "isDefault": false},
+                {"_type": "Priority", "id": 8, "name": "Normal", "position": 2, "isDefault": true},
+                {"_type": "Priority", "id": 9, "name": "Hoch", "position": 3, "isDefault": false},
+                {"_type": "Priority", "id": 10, "name": "Sofort", "position": 4, "isDefault": false}
+            ]
+        }
+    }
+    "#;
+    let priorities = parse_priorities(&json::parse(json_body).unwrap());
+    assert_eq!(HashMap::from([(7, 9), (9, 3), (10, 1)]), priorities);
+}
+
+#[test]
+fn open_project_saved_query_is_paginated() {
+    let mut server = mockito::Server::new();
+    let source = OpenProjectSource {
+        server_url: server.url(),
+        query_id: Some(5),
+        ..Default::default()
+    };
+    server
+        .mock("GET", "/api/v3/priorities")
+        .with_body(r#"{"_embedded": {"elements": []}}"#)
+        .create();
+    server
+        .mock("GET", "/api/v3/queries/5")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "pageSize".into(),
+            "100".into(),
+        ))
+        .with_body(
+            r#"{
+            "_type": "Query",
+            "_embedded": {"results": {
+                "_type": "WorkPackageCollection",
+                "_embedded": {"elements": [{"id": 1, "subject": "First", "_links": {}}]},
+                "_links": {"nextByOffset": {"href": "/api/v3/queries/5?offset=2&pageSize=100"}}
+            }}
+        }"#,
+        )
+        .create();
+    server
+        .mock("GET", "/api/v3/queries/5")
+        .match_query(mockito::Matcher::UrlEncoded("offset".into(), "2".into()))
+        .with_body(
+            r#"{
+            "_type": "Query",
+            "_embedded": {"results": {
+                "_type": "WorkPackageCollection",
+                "_embedded": {"elements": [{"id": 2, "subject": "Second", "_links": {}}]},
+                "_links": {}
+            }}
+        }"#,
+        )
+        .create();
+
+    let tasks = source.query_tasks(None::<&str>).unwrap();
+    let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(vec!["First", "Second"], titles);
 }
@@ -0,0 +1,56 @@
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::secrets::{secret_env_var, SecretStore};
+
+use super::{oauth::OAuthToken, GitLabSource, TaskSource};
+
+#[test]
+fn test_legacy_source_id_is_deterministic() {
+    // Settings of older versions have no ID for the sources
+    let settings = r#"GitLab((name: "Work", server_url: "https://gitlab.example.com/api/v4/"))"#;
+    let mut source: TaskSource = ron::from_str(settings).unwrap();
+    assert_eq!(Uuid::nil(), source.id());
+    source.migrate_legacy_settings();
+    let id = source.id();
+    assert!(!id.is_nil());
+
+    // The same ID is derived if the new ID has not been saved
+    let mut source: TaskSource = ron::from_str(settings).unwrap();
+    source.migrate_legacy_settings();
+    assert_eq!(id, source.id());
+
+    // Sources of other kinds or with other names get other IDs
+    let mut other: TaskSource =
+        ron::from_str(r#"GitHub((name: "Work", server_url: "https://api.github.com"))"#).unwrap();
+    other.migrate_legacy_settings();
+    assert_ne!(id, other.id());
+
+    // Renaming the source keeps its ID
+    let mut renamed = source.clone();
+    if let TaskSource::GitLab(s) = &mut renamed {
+        s.name = "Office".to_string();
+    }
+    renamed.migrate_legacy_settings();
+    assert_eq!(id, renamed.id());
+}
+
+#[test]
+fn test_provided_oauth_token_is_not_refreshed() {
+    let mut source = GitLabSource::default();
+    // Nothing listens on this port, so a refresh would fail differently
+    source.server_url = "http://127.0.0.1:9/api/v4/".to_string();
+    source.name = "Provided GitLab Token".to_string();
+    let source = TaskSource::GitLab(source);
+    let token = OAuthToken {
+        access_token: "access".to_string(),
+        refresh_token: Some("refresh".to_string()),
+        expires_at: Some(Utc::now()),
+    };
+    std::env::set_var(secret_env_var(source.name()), token.to_secret());
+
+    let result = source.secret(&SecretStore::default());
+    std::env::remove_var(secret_env_var(source.name()));
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("can't be refreshed"), "{error}");
+}
@@ -1,37 +1,124 @@
 use std::{
-    cmp::Ordering,
+    collections::BTreeSet,
     sync::{Arc, Mutex},
 };
 
-use anyhow::Result;
 use chrono::{DateTime, Utc};
 use eframe::epaint::ahash::HashMap;
-use keyring::Entry;
+use log::warn;
 #[cfg(test)]
 use mockall::mock;
 #[cfg(test)]
 use serde::Deserializer;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::sources::TaskSource;
+use crate::{
+    secrets::SecretStore,
+    sources::{SourceKind, TaskSource},
+};
+
+/// What kind of item in the tracker a task represents.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TaskKind {
+    #[default]
+    Todo,
+    Issue,
+    PullRequest,
+    MergeRequest,
+    WorkPackage,
+    ReviewRequest,
+}
+
+impl TaskKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskKind::Todo => "Todo",
+            TaskKind::Issue => "Issue",
+            TaskKind::PullRequest => "Pull request",
+            TaskKind::MergeRequest => "Merge request",
+            TaskKind::WorkPackage => "Work package",
+            TaskKind::ReviewRequest => "Review request",
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Why a task is on the list, e.g. because a review was requested. The
+/// sentence shown to the user is created from these fields.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TaskReason {
+    /// Name of the action in the tracker, e.g. `review_requested`.
+    pub action: String,
+    /// Who triggered the action, if it was not triggered automatically.
+    pub actor: Option<String>,
+    /// Type of the item in the tracker, e.g. `MergeRequest`.
+    pub target_type: Option<String>,
+    /// Reference of the item within its project, e.g. `!12`.
+    pub target_reference: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct Task {
     pub project: String,
     pub title: String,
     pub description: String,
+    /// The description is formatted with Markdown, otherwise it is plain
+    /// text.
+    pub markdown: bool,
     pub due: Option<DateTime<Utc>>,
     pub created: Option<DateTime<Utc>>,
+    /// ID of the task, which only needs to be unique within its source. Use
+    /// [`Task::get_id`] for an ID that is unique across all sources.
     pub id: Option<String>,
+    /// ID of the source this task has been queried from.
+    pub source_id: Uuid,
+    /// Name of the source this task has been queried from.
+    pub source_name: String,
+    pub source_kind: Option<SourceKind>,
+    pub kind: TaskKind,
+    pub start: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+    pub assignees: Vec<String>,
+    pub author: Option<String>,
+    /// Priority from 1 (highest) to 9 (lowest), like in iCalendar.
+    pub priority: Option<u8>,
+    /// How much of the task has been completed, in percent.
+    pub percent_complete: Option<u8>,
+    pub labels: Vec<String>,
+    pub location: Option<String>,
+    pub url: Option<String>,
+    /// Human readable status, if it is relevant for the user.
+    pub status: Option<String>,
+    /// Why the task is on the list, e.g. a review request by Alice.
+    pub reason: Option<TaskReason>,
+    /// Source specific ID of the parent task, if this is a subtask.
+    pub parent: Option<String>,
 }
 
 impl Task {
+    /// Qualify a source specific ID with the kind and ID of the source, so it
+    /// is unique across all sources and does not change when the source is
+    /// renamed.
+    fn scoped_id(&self, local_id: &str) -> String {
+        let kind = self.source_kind.map(|k| k.slug()).unwrap_or("task");
+        format!("{kind}:{}:{local_id}", self.source_id)
+    }
+
+    /// Returns an ID that is unique across all sources, e.g.
+    /// `openproject:<source ID>:33013`.
     pub fn get_id(&self) -> String {
         // Use provided ID or fall back to an auto-generated one
-        self.id
-            .as_ref()
-            .cloned()
-            .unwrap_or_else(|| format!("{}/{}", self.project, self.title))
+        match &self.id {
+            Some(id) => self.scoped_id(id),
+            None => self.scoped_id(&format!("{}/{}", self.project, self.title)),
+        }
+    }
+
+    /// Returns the ID of the parent task in the same form as [`Task::get_id`].
+    pub fn get_parent_id(&self) -> Option<String> {
+        self.parent.as_ref().map(|parent| self.scoped_id(parent))
     }
 }
 
@@ -40,22 +127,32 @@ impl Task {
 pub struct TaskManager {
     tasks: Arc<Mutex<Vec<Task>>>,
     sources: Vec<(TaskSource, bool)>,
+    secrets: SecretStore,
+    /// IDs of all sources for which a secret has been stored, so secrets of
+    /// removed sources can be cleaned up.
+    stored_secrets: BTreeSet<Uuid>,
+    /// Secrets stored by older versions under the source name have been
+    /// moved to entries for the source ID.
+    legacy_secrets_migrated: bool,
     #[serde(skip)]
-    error_by_source: Arc<Mutex<HashMap<String, anyhow::Error>>>,
+    error_by_source: Arc<Mutex<HashMap<Uuid, anyhow::Error>>>,
 }
 
-fn compare_optional<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
-    if let (Some(a), Some(b)) = (a, b) {
-        a.cmp(b)
-    } else if a.is_none() {
-        if b.is_none() {
-            Ordering::Equal
-        } else {
-            Ordering::Greater
+/// Get the due date of each task, which is the earliest due date of the task
+/// itself and all its subtasks.
+pub fn inherited_due_dates(tasks: &[Task]) -> HashMap<String, Option<DateTime<Utc>>> {
+    let mut result: HashMap<String, Option<DateTime<Utc>>> =
+        tasks.iter().map(|t| (t.get_id(), t.due)).collect();
+    for t in tasks {
+        if let (Some(parent), Some(due)) = (t.get_parent_id(), t.due) {
+            if let Some(parent_due) = result.get_mut(&parent) {
+                if parent_due.is_none_or(|parent_due| due < parent_due) {
+                    *parent_due = Some(due);
+                }
+            }
         }
-    } else {
-        Ordering::Less
     }
+    result
 }
 
 #[cfg(test)]
@@ -65,12 +162,17 @@ mock! {
 
         pub fn add_or_replace_source(&mut self, source: TaskSource, secret: &str);
         pub fn remove_source(&mut self, idx: usize) -> (TaskSource, bool);
+        pub fn restore_source(&mut self, source: TaskSource, enabled: bool, secret: &str);
+        pub fn cleanup_orphaned_secrets(&mut self);
+        pub fn migrate_legacy_settings(&mut self);
+        pub fn secrets(&self) -> &SecretStore;
+        pub fn set_secret_command(&mut self, command: String);
         pub fn refresh<F>(&mut self, finish_callback: F)
         where
             F: FnOnce() + Send + 'static;
         pub fn sources(&self) -> &Vec<(TaskSource, bool)>;
         pub fn source_ref_mut(&mut self, idx: usize) -> &mut (TaskSource, bool);
-        pub fn get_and_clear_last_err(&self, source: &str) -> Option<anyhow::Error>;
+        pub fn get_and_clear_last_err(&self, source: Uuid) -> Option<anyhow::Error>;
 
         fn private_deserialize(deserializable: Result<TaskManager, ()>) -> Self;
         fn private_serialize(&self) -> TaskManager;
@@ -96,20 +198,17 @@ impl<'de> Deserialize<'de> for MockTaskManager {
     }
 }
 
-/// Store a secret for a source in the keyring
-fn save_password(source_name: &str, secret: &str) -> Result<()> {
-    let keyring_entry = Entry::new("task-picker", source_name)?;
-    keyring_entry.set_password(secret)?;
-    Ok(())
-}
-
 impl TaskManager {
     /// Refresh task list in the background
     pub fn refresh<F>(&mut self, finish_callback: F)
     where
         F: FnOnce() + Send + 'static,
     {
+        for (source, _) in &mut self.sources {
+            source.migrate_legacy_settings();
+        }
         let sources = self.sources.clone();
+        let secrets = self.secrets.clone();
         let error_by_source = self.error_by_source.clone();
         let tasks = self.tasks.clone();
 
@@ -120,7 +219,13 @@ impl TaskManager {
             let mut new_errors = HashMap::default();
             for (source, active) in &sources {
                 if *active {
-                    let secret = source.secret();
+                    let secret = match source.secret(&secrets) {
+                        Ok(secret) => secret,
+                        Err(e) => {
+                            new_errors.insert(source.id(), e);
+                            continue;
+                        }
+                    };
                     let tasks_for_source = match source {
                         TaskSource::CalDav(s) => s.query_tasks(secret),
                         TaskSource::GitHub(s) => s.query_tasks(secret),
@@ -130,24 +235,12 @@ impl TaskManager {
                     match tasks_for_source {
                         Ok(tasks_for_source) => new_tasks.extend(tasks_for_source),
                         Err(e) => {
-                            new_errors.insert(source.name().to_string(), e);
+                            new_errors.insert(source.id(), e);
                         }
                     }
                 }
             }
 
-            // Show the tasks that are due next first. Tasks without due date are sorted
-            // by their creation date (oldest first).
-            new_tasks.sort_by(|a, b| {
-                let by_due_date = compare_optional(&a.due, &b.due);
-
-                if by_due_date == Ordering::Equal {
-                    compare_optional(&a.created, &b.created)
-                } else {
-                    by_due_date
-                }
-            });
-
             {
                 let mut tasks = tasks.lock().expect("Lock poisoning");
                 *tasks = new_tasks;
@@ -175,29 +268,104 @@ impl TaskManager {
     }
 
     /// Adds a new resource or replaces an existing one if a source with the
-    /// same name already exists.
+    /// same ID already exists. Sources are kept sorted by their name.
     pub fn add_or_replace_source(&mut self, source: TaskSource, secret: &str) {
-        let source_name = source.name().to_string();
-        let existing = self
-            .sources
-            .binary_search_by(|(probe, _)| probe.name().cmp(source.name()));
+        let source_id = source.id();
+        let save_result = self.secrets.set(&source, secret);
+        if save_result.is_ok() {
+            self.stored_secrets.insert(source_id);
+        }
+
+        let existing = self.sources.iter().position(|(s, _)| s.id() == source_id);
         match existing {
-            Ok(i) => self.sources[i].0 = source,
-            Err(i) => self.sources.insert(i, (source, true)),
+            Some(i) => {
+                self.sources[i].0 = source;
+            }
+            None => self.sources.push((source, true)),
         };
+        self.sources
+            .sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
 
-        if let Err(e) = save_password(&source_name, secret) {
+        if let Err(e) = save_result {
             let mut error_by_source = self.error_by_source.lock().expect("Lock poisoning");
-            error_by_source.insert(source_name, e);
+            error_by_source.insert(source_id, e);
         }
     }
 
+    /// Removes the source and deletes its stored secret.
     pub fn remove_source(&mut self, idx: usize) -> (TaskSource, bool) {
-        self.sources.remove(idx)
+        let (source, enabled) = self.sources.remove(idx);
+        match self.secrets.delete(source.id()) {
+            Ok(()) => {
+                self.stored_secrets.remove(&source.id());
+            }
+            Err(e) => warn!("Could not delete secret of source {}: {e}", source.name()),
+        }
+        (source, enabled)
+    }
+
+    /// Adds a previously removed source again, including its secret.
+    pub fn restore_source(&mut self, source: TaskSource, enabled: bool, secret: &str) {
+        let source_id = source.id();
+        self.add_or_replace_source(source, secret);
+        if let Some((_, e)) = self.sources.iter_mut().find(|(s, _)| s.id() == source_id) {
+            *e = enabled;
+        }
     }
 
-    pub fn get_and_clear_last_err(&self, source: &str) -> Option<anyhow::Error> {
+    /// Deletes stored secrets that belong to sources which do not exist
+    /// anymore, e.g. because removing the secret failed.
+    pub fn cleanup_orphaned_secrets(&mut self) {
+        let existing: BTreeSet<Uuid> = self.sources.iter().map(|(s, _)| s.id()).collect();
+        let orphaned: Vec<Uuid> = self.stored_secrets.difference(&existing).copied().collect();
+        for source_id in orphaned {
+            match self.secrets.delete(source_id) {
+                Ok(()) => {
+                    self.stored_secrets.remove(&source_id);
+                }
+                Err(e) => warn!("Could not delete orphaned secret {source_id}: {e}"),
+            }
+        }
+    }
+
+    /// Convert settings of sources that have been stored by older versions.
+    pub fn migrate_legacy_settings(&mut self) {
+        for (source, _) in &mut self.sources {
+            source.migrate_legacy_settings();
+        }
+        // Only sources that already existed in older versions can have a
+        // secret under their name, so this is done once
+        if !self.legacy_secrets_migrated {
+            let mut migrated = true;
+            for (source, _) in &self.sources {
+                match self.secrets.migrate_legacy(source) {
+                    Ok(true) => {
+                        self.stored_secrets.insert(source.id());
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        warn!("Could not migrate secret of source {}: {e}", source.name());
+                        migrated = false;
+                    }
+                }
+            }
+            self.legacy_secrets_migrated = migrated;
+        }
+    }
+
+    pub fn secrets(&self) -> &SecretStore {
+        &self.secrets
+    }
+
+    pub fn set_secret_command(&mut self, command: String) {
+        self.secrets.command = command;
+    }
+
+    pub fn get_and_clear_last_err(&self, source: Uuid) -> Option<anyhow::Error> {
         let mut error_by_source = self.error_by_source.lock().expect("Lock poisoning");
-        error_by_source.remove(source)
+        error_by_source.remove(&source)
     }
 }
+
+#[cfg(test)]
+mod tests;
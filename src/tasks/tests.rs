@@ -0,0 +1,41 @@
+use uuid::Uuid;
+
+use super::Task;
+use crate::sources::SourceKind;
+
+#[test]
+fn test_ids_are_scoped_by_source() {
+    let work_id = Uuid::from_u128(1);
+    let community_id = Uuid::from_u128(2);
+    let work = Task {
+        id: Some("33013".to_string()),
+        source_id: work_id,
+        source_name: "Work".to_string(),
+        source_kind: Some(SourceKind::OpenProject),
+        ..Default::default()
+    };
+    let community = Task {
+        source_id: community_id,
+        source_name: "Community".to_string(),
+        ..work.clone()
+    };
+    assert_eq!(format!("openproject:{work_id}:33013"), work.get_id());
+    assert_eq!(
+        format!("openproject:{community_id}:33013"),
+        community.get_id()
+    );
+
+    // Renaming the source does not change the ID
+    let renamed = Task {
+        source_name: "Office".to_string(),
+        ..work.clone()
+    };
+    assert_eq!(work.get_id(), renamed.get_id());
+
+    let subtask = Task {
+        id: Some("1".to_string()),
+        parent: Some("33013".to_string()),
+        ..work.clone()
+    };
+    assert_eq!(Some(work.get_id()), subtask.get_parent_id());
+}